@@ -1,11 +1,20 @@
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
 
+use itertools::Itertools;
+use toml_edit::{Document, Item, TableLike};
+
+/// A `coral-agent.toml` manifest, as read by the Coral server.
 #[derive(Debug, Deserialize)]
 pub struct CoralAgent {
+    pub edition: Option<i64>,
     pub agent: AgentDetails,
     pub options: Option<HashMap<String, AgentOption>>,
-    pub runtimes: Option<HashMap<String, serde_json::Value>>,
+    pub runtimes: Option<Runtimes>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -13,18 +22,537 @@ pub struct AgentDetails {
     pub name: String,
     pub version: String,
     pub description: Option<String>,
+    pub summary: Option<String>,
+    pub readme: Option<String>,
+    pub license: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub links: BTreeMap<String, String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct AgentOption {
     #[serde(rename = "type")]
-    pub kind: String,
+    pub kind: OptionType,
     pub required: Option<bool>,
     pub description: Option<String>,
+    pub default: Option<OptionValue>,
+    #[serde(default)]
+    pub secret: bool,
+}
+
+/// The default value of an agent option
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum OptionValue {
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
 }
 
+impl From<&OptionValue> for toml_edit::Value {
+    fn from(value: &OptionValue) -> Self {
+        match value {
+            OptionValue::Boolean(b) => (*b).into(),
+            OptionValue::Integer(i) => (*i).into(),
+            OptionValue::Float(f) => (*f).into(),
+            OptionValue::String(s) => s.as_str().into(),
+        }
+    }
+}
+
+/// The ways the Coral server can run an agent.
+#[derive(Debug, Deserialize)]
+pub struct Runtimes {
+    pub docker: Option<DockerRuntime>,
+    pub executable: Option<ExecutableRuntime>,
+    pub function: Option<FunctionRuntime>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DockerRuntime {
+    pub image: String,
+    pub transport: Option<Transport>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExecutableRuntime {
+    pub path: String,
+    #[serde(default)]
+    pub arguments: Vec<String>,
+    pub transport: Option<Transport>,
+}
+
+/// An agent running inside the Coral server's process, only available to JVM agents.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FunctionRuntime {}
+
+/// How an agent connects back to the Coral server's MCP endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    Sse,
+    StreamableHttp,
+}
+
+impl Transport {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Transport::Sse => "sse",
+            Transport::StreamableHttp => "streamable_http",
+        }
+    }
+}
+
+const RUNTIMES: [&str; 3] = ["docker", "executable", "function"];
+const TRANSPORTS: [&str; 2] = ["sse", "streamable_http"];
+
 impl CoralAgent {
     pub fn from_toml(content: &str) -> Result<Self, toml_edit::de::Error> {
         toml_edit::de::from_str(content)
     }
+
+    /// Reads the `coral-agent.toml` in `dir`, failing with every violation in it.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let toml_path = dir.join("coral-agent.toml");
+        if !toml_path.exists() {
+            anyhow::bail!("coral-agent.toml not found in {}", dir.display());
+        }
+
+        let content = fs::read_to_string(&toml_path)?;
+        Self::validate(&content).map_err(|violations| {
+            anyhow::anyhow!(
+                "Invalid coral-agent.toml:\n{}",
+                violations
+                    .iter()
+                    .map(|v| format!("  {}", v.render(&toml_path, &content)))
+                    .join("\n")
+            )
+        })
+    }
+
+    /// Parses a manifest, checking it against the schema and reporting every violation rather
+    /// than just the first.
+    pub fn validate(content: &str) -> Result<Self, Vec<Violation>> {
+        let doc = Document::parse(content).map_err(|e| {
+            vec![Violation {
+                path: String::new(),
+                message: e.message().to_string(),
+                span: e.span(),
+            }]
+        })?;
+
+        let mut v = Validator::default();
+        let root = doc.as_table();
+        v.optional(root, "", "edition", "an integer", Item::as_integer);
+        v.agent(root);
+        v.options(root);
+        v.runtimes(root);
+        if !v.violations.is_empty() {
+            return Err(v.violations);
+        }
+
+        // anything the schema walk missed still gets a location
+        Self::from_toml(content).map_err(|e| {
+            vec![Violation {
+                path: String::new(),
+                message: e.message().to_string(),
+                span: e.span(),
+            }]
+        })
+    }
+}
+
+/// A place where a manifest doesn't match the schema
+#[derive(Debug, Clone)]
+pub struct Violation {
+    /// Dotted path of the offending key, e.g. `options.API_KEY.type`
+    pub path: String,
+    pub message: String,
+    /// Byte range in the manifest
+    pub span: Option<Range<usize>>,
+}
+
+impl Violation {
+    /// 1-based line & column of the start of the span in `content`
+    pub fn line_col(&self, content: &str) -> Option<(usize, usize)> {
+        let start = self.span.as_ref()?.start.min(content.len());
+        let before = &content[..start];
+        let line = before.matches('\n').count() + 1;
+        let col = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        Some((line, col))
+    }
+
+    /// `file:line:col: path - message`
+    pub fn render(&self, file: &Path, content: &str) -> String {
+        match self.line_col(content) {
+            Some((line, col)) => format!("{}:{line}:{col}: {self}", file.display()),
+            None => format!("{}: {self}", file.display()),
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path.is_empty() {
+            true => write!(f, "{}", self.message),
+            false => write!(f, "'{}' - {}", self.path, self.message),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Validator {
+    violations: Vec<Violation>,
+}
+
+fn join(path: &str, key: &str) -> String {
+    match path.is_empty() {
+        true => key.to_string(),
+        false => format!("{path}.{key}"),
+    }
+}
+
+/// Where `parent[key]` is - implicit tables like `[runtimes]` in `[runtimes.docker]` have no key
+/// of their own, so fall back to their first entry
+fn table_span(parent: &dyn TableLike, key: &str, table: &dyn TableLike) -> Range<usize> {
+    parent
+        .key(key)
+        .and_then(|k| k.span())
+        .into_iter()
+        .chain(
+            table
+                .iter()
+                .filter_map(|(key, _)| table.key(key).and_then(|k| k.span())),
+        )
+        .next()
+        .unwrap_or(0..0)
+}
+
+impl Validator {
+    fn report(&mut self, path: String, span: Option<Range<usize>>, message: impl Into<String>) {
+        self.violations.push(Violation {
+            path,
+            message: message.into(),
+            span,
+        });
+    }
+
+    /// `table[key]` as a `T`, reporting when it's missing or of the wrong type
+    fn required<'a, T>(
+        &mut self,
+        table: &'a dyn TableLike,
+        span: Range<usize>,
+        path: &str,
+        key: &str,
+        expected: &str,
+        get: impl Fn(&'a Item) -> Option<T>,
+    ) -> Option<T> {
+        match table.get(key) {
+            Some(_) => self.optional(table, path, key, expected, get),
+            None => {
+                self.report(
+                    join(path, key),
+                    Some(span),
+                    format!("missing required key, expected {expected}"),
+                );
+                None
+            }
+        }
+    }
+
+    /// `table[key]` as a `T` if present, reporting when it's of the wrong type
+    fn optional<'a, T>(
+        &mut self,
+        table: &'a dyn TableLike,
+        path: &str,
+        key: &str,
+        expected: &str,
+        get: impl Fn(&'a Item) -> Option<T>,
+    ) -> Option<T> {
+        let item = table.get(key)?;
+        let value = get(item);
+        if value.is_none() {
+            self.report(
+                join(path, key),
+                item.span(),
+                format!("expected {expected}, found {}", item.type_name()),
+            );
+        }
+        value
+    }
+
+    fn string_array(&mut self, table: &dyn TableLike, path: &str, key: &str) {
+        let Some(array) = self.optional(table, path, key, "an array of strings", Item::as_array)
+        else {
+            return;
+        };
+        for (i, value) in array.iter().enumerate() {
+            if !value.is_str() {
+                self.report(
+                    format!("{}[{i}]", join(path, key)),
+                    value.span(),
+                    format!("expected a string, found {}", value.type_name()),
+                );
+            }
+        }
+    }
+
+    fn agent(&mut self, root: &dyn TableLike) {
+        let Some(agent) = self.required(root, 0..0, "", "agent", "a table", Item::as_table_like)
+        else {
+            return;
+        };
+        let span = table_span(root, "agent", agent);
+        if let Some(name) = self.required(
+            agent,
+            span.clone(),
+            "agent",
+            "name",
+            "a string",
+            Item::as_str,
+        ) && name.trim().is_empty()
+        {
+            self.report(
+                "agent.name".into(),
+                agent.get("name").and_then(Item::span),
+                "must not be empty",
+            );
+        }
+        self.required(agent, span, "agent", "version", "a string", Item::as_str);
+        for key in ["description", "summary", "readme", "license"] {
+            self.optional(agent, "agent", key, "a string", Item::as_str);
+        }
+        self.string_array(agent, "agent", "keywords");
+        if let Some(links) = self.optional(agent, "agent", "links", "a table", Item::as_table_like)
+        {
+            for (key, _) in links.iter() {
+                self.optional(links, "agent.links", key, "a string", Item::as_str);
+            }
+        }
+    }
+
+    fn options(&mut self, root: &dyn TableLike) {
+        let Some(options) = self.optional(root, "", "options", "a table", Item::as_table_like)
+        else {
+            return;
+        };
+        for (name, _) in options.iter() {
+            let path = join("options", name);
+            let Some(option) =
+                self.optional(options, "options", name, "a table", Item::as_table_like)
+            else {
+                continue;
+            };
+            let span = table_span(options, name, option);
+
+            let kind = self
+                .required(
+                    option,
+                    span.clone(),
+                    &path,
+                    "type",
+                    "a string",
+                    Item::as_str,
+                )
+                .and_then(|kind| match OptionType::parse(kind) {
+                    Some(kind) => Some(kind),
+                    None => {
+                        self.report(
+                            join(&path, "type"),
+                            option.get("type").and_then(Item::span),
+                            format!(
+                                "unknown option type '{kind}', expected one of {}",
+                                OptionType::ALL.iter().map(OptionType::as_str).join(", ")
+                            ),
+                        );
+                        None
+                    }
+                });
+            self.optional(option, &path, "description", "a string", Item::as_str);
+            let required = self.optional(option, &path, "required", "a boolean", Item::as_bool);
+            self.optional(option, &path, "secret", "a boolean", Item::as_bool);
+
+            let Some(default) = option.get("default") else {
+                continue;
+            };
+            if required == Some(true) {
+                self.report(
+                    join(&path, "default"),
+                    default.span(),
+                    "a required option can't have a default",
+                );
+            }
+            if let Some(kind) = kind
+                && !kind.accepts(default)
+            {
+                self.report(
+                    join(&path, "default"),
+                    default.span(),
+                    format!(
+                        "expected a default of type {}, found {}",
+                        kind.as_str(),
+                        default.type_name()
+                    ),
+                );
+            }
+        }
+    }
+
+    fn runtimes(&mut self, root: &dyn TableLike) {
+        let Some(runtimes) = self.optional(root, "", "runtimes", "a table", Item::as_table_like)
+        else {
+            return;
+        };
+        for (name, _) in runtimes.iter() {
+            let path = join("runtimes", name);
+            let Some(runtime) =
+                self.optional(runtimes, "runtimes", name, "a table", Item::as_table_like)
+            else {
+                continue;
+            };
+            let span = table_span(runtimes, name, runtime);
+            match name {
+                "docker" => {
+                    self.required(
+                        runtime,
+                        span.clone(),
+                        &path,
+                        "image",
+                        "a string",
+                        Item::as_str,
+                    );
+                }
+                "executable" => {
+                    self.required(
+                        runtime,
+                        span.clone(),
+                        &path,
+                        "path",
+                        "a string",
+                        Item::as_str,
+                    );
+                    self.string_array(runtime, &path, "arguments");
+                }
+                "function" => {}
+                _ => {
+                    self.report(
+                        path,
+                        Some(span),
+                        format!("unknown runtime, expected one of {}", RUNTIMES.join(", ")),
+                    );
+                    continue;
+                }
+            }
+            if let Some(transport) =
+                self.optional(runtime, &path, "transport", "a string", Item::as_str)
+                && !TRANSPORTS.contains(&transport)
+            {
+                self.report(
+                    join(&path, "transport"),
+                    runtime.get("transport").and_then(Item::span),
+                    format!(
+                        "unknown transport '{transport}', expected one of {}",
+                        TRANSPORTS.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// The type of an agent option's value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OptionType {
+    String,
+    Number,
+    #[serde(alias = "bool")]
+    Boolean,
+}
+
+impl OptionType {
+    pub const ALL: [OptionType; 3] = [OptionType::String, OptionType::Number, OptionType::Boolean];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OptionType::String => "string",
+            OptionType::Number => "number",
+            OptionType::Boolean => "boolean",
+        }
+    }
+
+    /// The type named `name` in a manifest
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "bool" => Some(OptionType::Boolean),
+            name => Self::ALL.into_iter().find(|t| t.as_str() == name),
+        }
+    }
+
+    /// Whether `item` is a valid value of this type
+    fn accepts(&self, item: &Item) -> bool {
+        match self {
+            OptionType::String => item.is_str(),
+            OptionType::Number => item.is_integer() || item.is_float(),
+            OptionType::Boolean => item.is_bool(),
+        }
+    }
+
+    /// The type a literal value (e.g. from an MCP config's env) looks like it has
+    pub fn infer(value: &str) -> Self {
+        if value.parse::<bool>().is_ok() {
+            OptionType::Boolean
+        } else if value.parse::<f64>().is_ok_and(f64::is_finite) {
+            OptionType::Number
+        } else {
+            OptionType::String
+        }
+    }
+
+    /// `value` as a value of this type, or as a string if it isn't one
+    pub fn value(&self, value: &str) -> OptionValue {
+        match self {
+            OptionType::Boolean => value.parse().map(OptionValue::Boolean).ok(),
+            OptionType::Number => value
+                .parse()
+                .map(OptionValue::Integer)
+                .or_else(|_| value.parse().map(OptionValue::Float))
+                .ok(),
+            OptionType::String => None,
+        }
+        .unwrap_or_else(|| OptionValue::String(value.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infers_option_types() {
+        assert_eq!(OptionType::infer("true"), OptionType::Boolean);
+        assert_eq!(OptionType::infer("8080"), OptionType::Number);
+        assert_eq!(OptionType::infer("0.5"), OptionType::Number);
+        assert_eq!(OptionType::infer("NaN"), OptionType::String);
+        assert_eq!(OptionType::infer("inf"), OptionType::String);
+        assert_eq!(OptionType::infer("yes"), OptionType::String);
+        assert_eq!(OptionType::infer(""), OptionType::String);
+    }
+
+    #[test]
+    fn typed_values() {
+        assert_eq!(OptionType::Number.value("3"), OptionValue::Integer(3));
+        assert_eq!(OptionType::Number.value("0.5"), OptionValue::Float(0.5));
+        assert_eq!(
+            OptionType::Boolean.value("false"),
+            OptionValue::Boolean(false)
+        );
+        assert_eq!(
+            OptionType::Boolean.value("maybe"),
+            OptionValue::String("maybe".to_string())
+        );
+    }
 }
@@ -0,0 +1,161 @@
+use std::{
+    fs,
+    io::{IsTerminal as _, stdin},
+    path::Path,
+};
+
+use inquire::{InquireError, error::InquireResult};
+use serde::Deserialize;
+
+/// Pre-filled answers for the prompts asked while scaffolding, loaded from an `--answers` file and
+/// overridden by any matching command line flags.
+///
+/// ```toml
+/// name = "my-agent"
+/// framework = "langchain"
+/// overwrite = true
+/// docker_image = "my-org/my-agent:latest"
+/// description = "Reads and writes files on behalf of other agents."
+/// llm_provider = "ollama"
+/// llm_model = "llama3.2"
+/// llm_base_url = "http://localhost:11434"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Answers {
+    pub name: Option<String>,
+    pub framework: Option<String>,
+    pub overwrite: Option<bool>,
+    pub docker_image: Option<String>,
+    pub description: Option<String>,
+    pub llm_provider: Option<String>,
+    pub llm_model: Option<String>,
+    pub llm_base_url: Option<String>,
+
+    #[serde(skip)]
+    interactive: bool,
+    #[serde(skip)]
+    yes: bool,
+}
+
+impl Answers {
+    /// Loads answers from `path` (if any). Prompts are only shown when `yes` is not set and stdin is
+    /// a terminal, otherwise every missing answer is an error.
+    pub fn load(path: Option<&Path>, yes: bool) -> InquireResult<Self> {
+        let mut answers: Answers = match path {
+            Some(path) => {
+                let contents = fs::read_to_string(path)?;
+                toml_edit::de::from_str(&contents).map_err(|e| {
+                    InquireError::Custom(
+                        format!("invalid answers file '{}' - {e}", path.display()).into(),
+                    )
+                })?
+            }
+            None => Answers::default(),
+        };
+        answers.interactive = !yes && stdin().is_terminal();
+        answers.yes = yes;
+        Ok(answers)
+    }
+
+    /// Returns `answer` if there is one, otherwise asks `prompt` - or fails when non-interactive.
+    pub fn answer_or_prompt<T>(
+        &self,
+        answer: Option<T>,
+        key: &str,
+        prompt: impl FnOnce() -> InquireResult<T>,
+    ) -> InquireResult<T> {
+        match answer {
+            Some(answer) => Ok(answer),
+            None if self.interactive => prompt(),
+            None => Err(missing_answer(key)),
+        }
+    }
+
+    /// Asks `prompt` to confirm or change `default` - or just takes it when non-interactive.
+    pub fn prompt_or<T>(
+        &self,
+        default: T,
+        prompt: impl FnOnce(T) -> InquireResult<T>,
+    ) -> InquireResult<T> {
+        match self.interactive {
+            true => prompt(default),
+            false => Ok(default),
+        }
+    }
+
+    /// Asks to confirm running `command` - which `--yes` agrees to up front, and which fails
+    /// when otherwise non-interactive.
+    pub fn confirm_run(&self, command: &str) -> anyhow::Result<bool> {
+        match (self.yes, self.interactive) {
+            (true, _) => Ok(true),
+            (false, true) => Ok(
+                inquire::Confirm::new(&format!("Run `{command}` in the agent?"))
+                    .with_default(true)
+                    .prompt()?,
+            ),
+            (false, false) => {
+                anyhow::bail!("`{command}` needs confirming in non-interactive mode - pass `--yes`")
+            }
+        }
+    }
+}
+
+pub fn missing_answer(key: &str) -> InquireError {
+    InquireError::Custom(
+        format!(
+            "no answer for '{key}' in non-interactive mode - pass `--{}` or set `{key}` in the --answers file",
+            key.replace('_', "-")
+        )
+        .into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answered_values_are_taken() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("answers.toml");
+        fs::write(&path, "name = \"my-agent\"\noverwrite = true\n").unwrap();
+        let answers = Answers::load(Some(&path), true).unwrap();
+
+        let name = answers.answer_or_prompt(answers.name.clone(), "name", || {
+            panic!("answered values aren't asked for")
+        });
+        assert_eq!(name.unwrap(), "my-agent");
+        assert_eq!(answers.overwrite, Some(true));
+        assert_eq!(answers.prompt_or(3, |_| panic!("not asked")).unwrap(), 3);
+        assert!(answers.confirm_run("npm install").unwrap());
+    }
+
+    #[test]
+    fn missing_answers_fail_without_a_terminal() {
+        let answers = Answers::default();
+        let e = answers
+            .answer_or_prompt(answers.docker_image.clone(), "docker_image", || {
+                panic!("nothing is asked without a terminal")
+            })
+            .unwrap_err();
+        let InquireError::Custom(e) = e else {
+            panic!("expected a custom error, got {e:?}");
+        };
+        let message = e.to_string();
+        assert!(message.contains("'docker_image'"), "{message}");
+        assert!(message.contains("--docker-image"), "{message}");
+
+        let e = answers.confirm_run("npm install").unwrap_err();
+        assert!(e.to_string().contains("--yes"), "{e}");
+    }
+
+    #[test]
+    fn unknown_answers_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("answers.toml");
+        fs::write(&path, "nmae = \"typo\"\n").unwrap();
+        let e = Answers::load(Some(&path), true).unwrap_err();
+        assert!(e.to_string().contains("invalid answers file"), "{e}");
+    }
+}
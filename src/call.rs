@@ -0,0 +1,373 @@
+use std::borrow::Cow;
+
+use colored::Colorize as _;
+use inquire::{
+    Autocomplete, CustomUserError, InquireError,
+    autocompletion::Replacement,
+    error::InquireResult,
+    validator::{ErrorMessage, Validation},
+};
+use itertools::Itertools;
+use rmcp::model::Tool;
+use rmcp::model::{CallToolRequestParam, CallToolResult, JsonObject, RawContent, ResourceContents};
+use serde_json::Value;
+
+use crate::mcp_client::Client;
+use crate::mcp_env::McpEnv;
+
+const SKIP: &str = "(skip)";
+
+/// Repeatedly asks for a tool of `client`'s server, prompts for its arguments and prints what it
+/// returns. Escaping the tool prompt leaves the REPL, escaping an argument goes back to it.
+pub async fn repl(client: &Client, env: &McpEnv) -> InquireResult<()> {
+    let tools = client
+        .list_all_tools()
+        .await
+        .map_err(|e| InquireError::Custom(format!("failed to list tools - {e}").into()))?;
+    if tools.is_empty() {
+        return Err(InquireError::Custom("the server has no tools".into()));
+    }
+    let names = tools
+        .iter()
+        .map(|t| t.name.to_string())
+        .sorted()
+        .collect_vec();
+    println!(
+        "{} tools: {}",
+        tools.len(),
+        names.iter().map(|n| n.green()).join(", ")
+    );
+
+    loop {
+        let known = names.clone();
+        let name = match inquire::Text::new("Tool")
+            .with_help_message("tab to complete, esc to quit")
+            .with_autocomplete(ToolNames(names.clone()))
+            .with_validator(move |input: &str| {
+                Ok(match known.iter().any(|name| name == input.trim()) {
+                    true => Validation::Valid,
+                    false => Validation::Invalid(ErrorMessage::Custom(format!(
+                        "no tool named '{}'",
+                        input.trim()
+                    ))),
+                })
+            })
+            .prompt()
+        {
+            Ok(name) => name.trim().to_string(),
+            Err(InquireError::OperationCanceled) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let tool = tools
+            .iter()
+            .find(|t| t.name == name)
+            .expect("validated tool name");
+        if let Some(description) = &tool.description {
+            println!("{}", description.dimmed());
+        }
+
+        let arguments = match prompt_arguments(tool) {
+            Ok(arguments) => arguments,
+            Err(InquireError::OperationCanceled) => continue,
+            Err(e) => return Err(e),
+        };
+        let request = CallToolRequestParam {
+            name: Cow::Owned(name.clone()),
+            arguments: Some(arguments),
+        };
+        match client.peer().call_tool(request).await {
+            Ok(result) => print_result(&result),
+            Err(e) => println!("{} {}", "Error:".red(), env.redact(&e.to_string())),
+        }
+    }
+}
+
+/// Suggests the tool names containing what's typed, and completes to the highlighted one - or to
+/// as much as the matching names have in common
+#[derive(Clone)]
+struct ToolNames(Vec<String>);
+
+impl ToolNames {
+    fn matching(&self, input: &str) -> Vec<String> {
+        let input = input.to_lowercase();
+        self.0
+            .iter()
+            .filter(|name| name.to_lowercase().contains(&input))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Autocomplete for ToolNames {
+    fn get_suggestions(&mut self, input: &str) -> Result<Vec<String>, CustomUserError> {
+        Ok(self.matching(input))
+    }
+
+    fn get_completion(
+        &mut self,
+        input: &str,
+        highlighted_suggestion: Option<String>,
+    ) -> Result<Replacement, CustomUserError> {
+        if highlighted_suggestion.is_some() {
+            return Ok(highlighted_suggestion);
+        }
+        let matching = self.matching(input);
+        let Some((first, rest)) = matching.split_first() else {
+            return Ok(None);
+        };
+        let common = rest.iter().fold(first.as_str(), |common, name| {
+            let len = common
+                .chars()
+                .zip(name.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a.len_utf8())
+                .sum();
+            &common[..len]
+        });
+        Ok((common.len() > input.len()).then(|| common.to_string()))
+    }
+}
+
+/// Prompts for every property of the tool's input schema, required ones first
+fn prompt_arguments(tool: &Tool) -> InquireResult<JsonObject> {
+    let required = tool
+        .input_schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|r| r.iter().filter_map(Value::as_str).collect_vec())
+        .unwrap_or_default();
+    let properties = tool
+        .input_schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+        .sorted_by_key(|(name, _)| (!required.contains(&name.as_str()), *name));
+
+    let mut arguments = JsonObject::new();
+    for (name, schema) in properties {
+        if let Some(value) = prompt_argument(name, schema, required.contains(&name.as_str()))? {
+            arguments.insert(name.clone(), value);
+        }
+    }
+    Ok(arguments)
+}
+
+/// Prompts for a single argument, returning `None` if an optional one is skipped
+fn prompt_argument(name: &str, schema: &Value, required: bool) -> InquireResult<Option<Value>> {
+    let kind = kind_of(schema);
+    let message = match required {
+        true => name.to_string(),
+        false => format!("{name} (optional)"),
+    };
+    let help = match schema.get("description").and_then(Value::as_str) {
+        Some(description) => format!("{kind} - {description}"),
+        None => kind.to_string(),
+    };
+
+    if let Some(variants) = schema.get("enum").and_then(Value::as_array) {
+        let mut options = variants.iter().map(display_value).collect_vec();
+        if !required {
+            options.insert(0, SKIP.to_string());
+        }
+        let picked = inquire::Select::new(&message, options)
+            .with_help_message(&help)
+            .raw_prompt()?;
+        return Ok(match required {
+            true => Some(variants[picked.index].clone()),
+            false => picked.index.checked_sub(1).map(|i| variants[i].clone()),
+        });
+    }
+
+    if kind == "boolean" {
+        if required {
+            let default = schema.get("default").and_then(Value::as_bool);
+            return Ok(Some(Value::Bool(
+                inquire::Confirm::new(&message)
+                    .with_default(default.unwrap_or(false))
+                    .with_help_message(&help)
+                    .prompt()?,
+            )));
+        }
+        let picked = inquire::Select::new(&message, vec![SKIP, "true", "false"])
+            .with_help_message(&help)
+            .prompt()?;
+        return Ok(picked.parse().ok().map(Value::Bool));
+    }
+
+    let placeholder = schema.get("default").map(display_value);
+    let validated_kind = kind.to_string();
+    let mut prompt = inquire::Text::new(&message)
+        .with_help_message(&help)
+        .with_validator(move |input: &str| {
+            Ok(
+                match (input.is_empty(), parse_argument(&validated_kind, input)) {
+                    (true, _) if !required => Validation::Valid,
+                    (_, Ok(_)) => Validation::Valid,
+                    (_, Err(e)) => Validation::Invalid(ErrorMessage::Custom(e)),
+                },
+            )
+        });
+    if let Some(placeholder) = &placeholder {
+        prompt = prompt.with_placeholder(placeholder);
+    }
+    let input = prompt.prompt()?;
+    if input.is_empty() && !required {
+        return Ok(None);
+    }
+    // checked by the validator
+    Ok(parse_argument(kind, &input).ok())
+}
+
+/// The schema's JSON type - `"type": ["string", "null"]` is as good as `"type": "string"`
+fn kind_of(schema: &Value) -> &str {
+    match schema.get("type") {
+        Some(Value::String(kind)) => kind,
+        Some(Value::Array(kinds)) => kinds
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|k| *k != "null")
+            .unwrap_or("any"),
+        _ => "any",
+    }
+}
+
+/// Parses what was typed for an argument of JSON schema type `kind`. Strings are taken as-is,
+/// anything that isn't a number or a string must be written as JSON.
+fn parse_argument(kind: &str, input: &str) -> Result<Value, String> {
+    match kind {
+        "string" => Ok(Value::String(input.to_string())),
+        "integer" => input
+            .trim()
+            .parse::<i64>()
+            .map(Value::from)
+            .map_err(|_| "not an integer".to_string()),
+        "number" => input
+            .trim()
+            .parse::<serde_json::Number>()
+            .map(Value::Number)
+            .map_err(|_| "not a number".to_string()),
+        "any" => Ok(serde_json::from_str(input).unwrap_or_else(|_| input.to_string().into())),
+        kind => serde_json::from_str(input).map_err(|e| format!("not valid JSON ({kind}) - {e}")),
+    }
+}
+
+/// Strings without their quotes, anything else as JSON
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn print_result(result: &CallToolResult) {
+    if result.is_error == Some(true) {
+        println!("{}", "The tool returned an error:".red());
+    }
+    for content in &result.content {
+        match &content.raw {
+            RawContent::Text(text) => println!("{}", pretty(&text.text)),
+            RawContent::Image(image) => {
+                println!("{}", format!("[image {}]", image.mime_type).dimmed())
+            }
+            RawContent::Audio(audio) => {
+                println!("{}", format!("[audio {}]", audio.mime_type).dimmed())
+            }
+            RawContent::Resource(resource) => match &resource.resource {
+                ResourceContents::TextResourceContents { uri, text, .. } => {
+                    println!("{}", uri.blue());
+                    println!("{}", pretty(text));
+                }
+                ResourceContents::BlobResourceContents { uri, mime_type, .. } => println!(
+                    "{} {}",
+                    uri.blue(),
+                    format!("[blob {}]", mime_type.as_deref().unwrap_or("unknown")).dimmed()
+                ),
+            },
+            RawContent::ResourceLink(link) => println!("{} {}", link.uri.blue(), link.name),
+        }
+    }
+    // servers usually send structured content as text too
+    if result.content.is_empty()
+        && let Some(structured) = &result.structured_content
+    {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(structured).unwrap_or_default()
+        );
+    }
+}
+
+/// Text as it is, unless it's a JSON object or array which is pretty-printed
+fn pretty(text: &str) -> String {
+    match serde_json::from_str::<Value>(text) {
+        Ok(json @ (Value::Object(_) | Value::Array(_))) => {
+            serde_json::to_string_pretty(&json).unwrap_or_else(|_| text.to_string())
+        }
+        _ => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn kinds_of_schemas() {
+        assert_eq!(kind_of(&json!({"type": "integer"})), "integer");
+        assert_eq!(kind_of(&json!({"type": ["null", "string"]})), "string");
+        assert_eq!(kind_of(&json!({"type": ["null"]})), "any");
+        assert_eq!(kind_of(&json!({"description": "anything"})), "any");
+    }
+
+    #[test]
+    fn parses_arguments_by_kind() {
+        assert_eq!(parse_argument("string", " 42 "), Ok(json!(" 42 ")));
+        assert_eq!(parse_argument("integer", " 42 "), Ok(json!(42)));
+        assert_eq!(parse_argument("integer", "-7"), Ok(json!(-7)));
+        assert_eq!(parse_argument("number", "1.5"), Ok(json!(1.5)));
+        assert_eq!(parse_argument("number", "3"), Ok(json!(3)));
+        assert_eq!(parse_argument("boolean", "true"), Ok(json!(true)));
+        assert_eq!(parse_argument("array", r#"[1, "a"]"#), Ok(json!([1, "a"])));
+        assert_eq!(
+            parse_argument("object", r#"{"path": "/tmp", "depth": 2}"#),
+            Ok(json!({"path": "/tmp", "depth": 2}))
+        );
+        // anything goes without a type - as JSON if it is JSON
+        assert_eq!(parse_argument("any", "[1]"), Ok(json!([1])));
+        assert_eq!(parse_argument("any", "hello"), Ok(json!("hello")));
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert_eq!(
+            parse_argument("integer", "1.5"),
+            Err("not an integer".to_string())
+        );
+        assert_eq!(
+            parse_argument("integer", "ten"),
+            Err("not an integer".to_string())
+        );
+        assert_eq!(
+            parse_argument("number", "1,5"),
+            Err("not a number".to_string())
+        );
+        for (kind, input) in [
+            ("boolean", "yes"),
+            ("array", "[1,"),
+            ("object", "path=/tmp"),
+        ] {
+            let e = parse_argument(kind, input).unwrap_err();
+            assert!(e.starts_with(&format!("not valid JSON ({kind})")), "{e}");
+        }
+    }
+
+    #[test]
+    fn displays_strings_without_quotes() {
+        assert_eq!(display_value(&json!("a \"b\"")), "a \"b\"");
+        assert_eq!(display_value(&json!(1.5)), "1.5");
+        assert_eq!(display_value(&json!({"a": [true]})), r#"{"a":[true]}"#);
+    }
+}
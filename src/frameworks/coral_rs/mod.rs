@@ -1,17 +1,18 @@
-use std::collections::HashSet;
-use std::fmt::Write as _;
+use std::collections::{HashMap, HashSet};
 use std::io;
 use std::path::Path;
 use std::sync::Arc;
 
+use itertools::Itertools;
 use quote::quote;
 use regex::Regex;
 use toml_edit::{DocumentMut, value};
 
 use crate::Runtime;
 use crate::edit::edit_file_str;
-use crate::frameworks::Template;
+use crate::frameworks::{Template, find_closing};
 use crate::mcp_server::{McpServer, McpServers};
+use crate::templates::TemplateSource;
 
 #[derive(Clone)]
 pub struct CoralRs {
@@ -23,13 +24,16 @@ impl Template for CoralRs {
     fn name(&self) -> &'static str {
         "coral-rs"
     }
-    fn artifact(&self) -> (&'static str, &'static str) {
-        (
-            "https://github.com/Coral-Protocol/coral-rs-agent/archive/d55baba502dd17e8a885b4f0d4b70c7613351834.zip",
-            "d55baba502dd17e8a885b4f0d4b70c7613351834.zip",
-        )
+    fn default_source(&self) -> TemplateSource {
+        TemplateSource::Git {
+            git: "https://github.com/Coral-Protocol/coral-rs-agent".to_string(),
+            rev: "d55baba502dd17e8a885b4f0d4b70c7613351834".to_string(),
+            // TODO: pin the archive of `rev` - until then, the SHA-256 recorded when it's first
+            // downloaded guards the cached copy
+            sha256: None,
+        }
     }
-    fn include_file(entry: &ignore::DirEntry) -> bool {
+    fn include_file(&self, entry: &ignore::DirEntry) -> bool {
         if entry
             .file_name()
             .to_str()
@@ -46,53 +50,47 @@ impl Template for CoralRs {
         }
         false
     }
-    fn template(&self, contents: &str) -> String {
+    fn template(&self, contents: &str) -> anyhow::Result<Option<String>> {
         let mcp_client_re =
             Regex::new(r#"let mut agent = Agent::new\((?:.|\w|\n)*?(\n\w*\n)"#).unwrap();
+        let Some(m) = mcp_client_re.find(contents) else {
+            return Ok(None);
+        };
         let mut contents = contents.to_string();
-        if let Some(m) = mcp_client_re.find(&contents) {
-            let mut s = String::new();
-            writeln!(s, ",").unwrap();
-            let mut servers = vec![];
-            for (mcp_name, mcp) in &self.mcps.servers {
-                // TODO (alan): dedupe this
-                servers.push(match mcp {
-                    McpServer::Stdio { command, args, env } => {
-                        if let Some(e) = &env && !e.is_empty() {
-                            eprintln!("coral-rs does not support passing environment variables to stdio MCP Servers!");
-                        }
-                        let err_msg = format!("failed to spawn stdio mcp server '{mcp_name}'");
-                        quote! {
-                            mcp_server(McpConnectionBuilder::stdio(#command, [#(#args),*], #mcp_name).connect().await.expect(#err_msg))
-                        }
-                    }
-                    McpServer::Http { .. } => {
-                        eprintln!("MCP Servers with http transport not supported in coral-rs!");
-                        continue;
-                    }
-                    McpServer::Sse { url, headers } => {
-                        let err_msg = format!("failed to connect to sse mcp server '{mcp_name}'");
-                        if let Some(h) = &headers && !h.is_empty() {
-                            eprintln!("coral-rs does not support passing headers to SSE MCP Servers!");
-                        }
-                        quote! {
-                            mcp_server(McpConnectionBuilder::sse(#url).connect().await.expect(#err_msg))
-                        }
-                    }
-                });
-            }
-            let servers = servers.into_iter().fold(quote! {agent}, |acc, ident| {
-                quote! { #acc.#ident }
-            });
-            let tokens = quote! {
-                agent = #servers;
+        contents.insert_str(m.end() + 1, &self.servers_code("    ")?);
+        Ok(Some(contents))
+    }
+
+    fn render_servers(&self, indent: &str) -> anyhow::Result<String> {
+        self.servers_code(indent)
+    }
+
+    fn remove_server(&self, contents: &str, name: &str) -> Option<(String, String)> {
+        let call_re = Regex::new(r#"\s*\.\s*mcp_server\s*\("#).unwrap();
+        let empty_re = Regex::new(r#"(?m)^[ \t]*agent\s*=\s*agent\s*;[ \t]*\n?"#).unwrap();
+        for m in call_re.find_iter(contents) {
+            let Some(close) = find_closing(contents, m.end() - 1, b"\"") else {
+                continue;
             };
-            let text = format!("    {tokens}\n");
-            contents.insert_str(m.end() + 1, &text);
-        } else {
-            panic!("bad");
+            let call = &contents[m.start()..=close];
+            if !connects(call, name) {
+                continue;
+            }
+            let contents = format!("{}{}", &contents[..m.start()], &contents[close + 1..]);
+
+            // the removed server was the only one in its chain
+            let contents = empty_re.replace(&contents, "").into_owned();
+            return Some((contents, call.trim_start().to_string()));
         }
-        contents
+        None
+    }
+
+    fn server_options(&self, source: &str) -> Vec<String> {
+        let printenv_re = Regex::new(r#"printenv '([^']*)'"#).unwrap();
+        printenv_re
+            .captures_iter(source)
+            .map(|caps| caps[1].to_string())
+            .collect()
     }
 
     fn post_process(&self, root: &Path, agent_name: &str) -> std::io::Result<()> {
@@ -104,16 +102,7 @@ impl Template for CoralRs {
             eprintln!("error modifying Cargo.toml - {e:?}");
         }
 
-        match std::process::Command::new("cargo")
-            .arg("fmt")
-            .current_dir(root)
-            .spawn()
-        {
-            Ok(_) => println!("Formatted"),
-            Err(_) => {
-                eprintln!("Failed to format coralized project. Code may look weird.");
-            }
-        }
+        cargo_fmt(root);
 
         // if self.runtimes.contains(&Runtime::Npx) {
         //     print!("🔧 {:>18} fixup", style("'Dockerfile'").blue());
@@ -137,4 +126,223 @@ impl Template for CoralRs {
 
         Ok(())
     }
+
+    fn post_inject(&self, root: &Path) -> std::io::Result<()> {
+        cargo_fmt(root);
+        Ok(())
+    }
+}
+
+impl CoralRs {
+    /// The MCP servers' code, each line indented by `indent`
+    fn servers_code(&self, indent: &str) -> anyhow::Result<String> {
+        let mut servers = vec![];
+        for (mcp_name, mcp) in &self.mcps.servers {
+            // TODO (alan): dedupe this
+            servers.push(match mcp {
+                McpServer::Stdio { command, args, env, cwd, env_file } => {
+                    let (command, args) = match shell_prelude(env, cwd, env_file) {
+                        Some(prelude) => {
+                            let script = format!(r#"{prelude} && exec "$0" "$@""#);
+                            let args = ["-c".to_string(), script, command.clone()]
+                                .into_iter()
+                                .chain(args.iter().cloned())
+                                .collect();
+                            ("sh".to_string(), args)
+                        }
+                        None => (command.clone(), args.clone()),
+                    };
+                    let err_msg = format!("failed to spawn stdio mcp server '{mcp_name}'");
+                    quote! {
+                        mcp_server(McpConnectionBuilder::stdio(#command, [#(#args),*], #mcp_name).connect().await.expect(#err_msg))
+                    }
+                }
+                McpServer::Http { .. } => {
+                    anyhow::bail!(
+                        "MCP server '{mcp_name}' uses the http transport, which coral-rs doesn't \
+                         support"
+                    );
+                }
+                McpServer::Sse { url, headers } => {
+                    let err_msg = format!("failed to connect to sse mcp server '{mcp_name}'");
+                    if let Some(h) = &headers && !h.is_empty() {
+                        eprintln!("coral-rs does not support passing headers to SSE MCP Servers!");
+                    }
+                    quote! {
+                        mcp_server(McpConnectionBuilder::sse(#url).connect().await.expect(#err_msg))
+                    }
+                }
+            });
+        }
+        let servers = servers.into_iter().fold(quote! {agent}, |acc, ident| {
+            quote! { #acc.#ident }
+        });
+        let tokens = quote! {
+            agent = #servers;
+        };
+        Ok(format!("{indent}{tokens}\n"))
+    }
+}
+
+/// Whether the `.mcp_server(...)` `call` connects to the server `name` - by the name a stdio
+/// connection is given, or the error message of an SSE connection, which has no name
+fn connects(call: &str, name: &str) -> bool {
+    let builder_re = Regex::new(r#"McpConnectionBuilder\s*::\s*(stdio|sse)\s*\("#).unwrap();
+    let Some(caps) = builder_re.captures(call) else {
+        return false;
+    };
+    if &caps[1] == "sse" {
+        return call.contains(&format!("sse mcp server '{name}'"));
+    }
+    let open = caps.get(0).expect("match").end() - 1;
+    let Some(close) = find_closing(call, open, b"\"") else {
+        return false;
+    };
+    // the name is the last argument
+    let literal = quote!(#name).to_string();
+    call[open + 1..close]
+        .trim_end()
+        .trim_end_matches(',')
+        .trim_end()
+        .ends_with(&literal)
+}
+
+/// coral-rs can only spawn a plain command, so a working directory, env file or env vars are set
+/// up by wrapping the command in `sh`. Env vars are exported even when named the same as their
+/// options, so the code says which options each server uses.
+fn shell_prelude(
+    env: &Option<HashMap<String, String>>,
+    cwd: &Option<String>,
+    env_file: &Option<String>,
+) -> Option<String> {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', r"'\''"));
+    let mut steps = vec![];
+    if let Some(cwd) = cwd {
+        steps.push(format!("cd {}", quote(cwd)));
+    }
+    if let Some(env_file) = env_file {
+        steps.push(format!("set -a && . {} && set +a", quote(env_file)));
+    }
+    // options are passed to the agent as env vars
+    for (env, opt) in env.iter().flatten().sorted() {
+        steps.push(format!("export {env}=\"$(printenv {})\"", quote(opt)));
+    }
+    (!steps.is_empty()).then(|| steps.join(" && "))
+}
+
+fn cargo_fmt(root: &Path) {
+    match std::process::Command::new("cargo")
+        .arg("fmt")
+        .current_dir(root)
+        .spawn()
+    {
+        Ok(_) => println!("Formatted"),
+        Err(_) => {
+            eprintln!("Failed to format coralized project. Code may look weird.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stdio(env: &[(&str, &str)]) -> McpServer {
+        McpServer::Stdio {
+            command: "server".to_string(),
+            args: vec![],
+            env: Some(
+                env.iter()
+                    .map(|(env, opt)| (env.to_string(), opt.to_string()))
+                    .collect(),
+            ),
+            cwd: None,
+            env_file: None,
+        }
+    }
+
+    fn agent(servers: &[(&str, McpServer)]) -> (CoralRs, String) {
+        let mcps = McpServers {
+            servers: servers
+                .iter()
+                .map(|(name, server)| (name.to_string(), server.clone()))
+                .collect(),
+        };
+        let coral_rs = CoralRs {
+            runtimes: Arc::default(),
+            mcps: Arc::new(mcps),
+        };
+        let contents = format!(
+            "    let mut agent = Agent::new(completion);\n{}",
+            coral_rs.servers_code("    ").unwrap()
+        );
+        (coral_rs, contents)
+    }
+
+    #[test]
+    fn removed_server_options_include_same_named_env_vars() {
+        let (coral_rs, contents) = agent(&[
+            ("a", stdio(&[("API_KEY", "A_KEY"), ("SHARED", "SHARED")])),
+            ("ba", stdio(&[("SHARED", "SHARED")])),
+        ]);
+        let (remaining, removed) = coral_rs.remove_server(&contents, "a").unwrap();
+
+        assert_eq!(
+            coral_rs
+                .server_options(&removed)
+                .into_iter()
+                .sorted()
+                .collect_vec(),
+            ["A_KEY", "SHARED"]
+        );
+        assert_eq!(coral_rs.server_options(&remaining), ["SHARED"]);
+        assert!(coral_rs.remove_server(&remaining, "a").is_none());
+        assert!(coral_rs.remove_server(&remaining, "ba").is_some());
+    }
+
+    #[test]
+    fn removes_the_last_server_of_a_chain() {
+        let (coral_rs, contents) = agent(&[("a", stdio(&[]))]);
+        let (remaining, _) = coral_rs.remove_server(&contents, "a").unwrap();
+        assert_eq!(remaining, "    let mut agent = Agent::new(completion);\n");
+    }
+
+    #[test]
+    fn skips_unbalanced_calls() {
+        let (coral_rs, contents) = agent(&[("a", stdio(&[]))]);
+        let contents = format!("    // agent.mcp_server(\n{contents}");
+        assert!(coral_rs.remove_server(&contents, "a").is_some());
+    }
+
+    #[test]
+    fn matches_sse_servers_by_their_error_message() {
+        let sse = McpServer::Sse {
+            url: "http://localhost/sse".to_string(),
+            headers: None,
+        };
+        let (coral_rs, contents) = agent(&[("remote", sse)]);
+        assert!(coral_rs.remove_server(&contents, "remote").is_some());
+        assert!(coral_rs.remove_server(&contents, "other").is_none());
+    }
+
+    #[test]
+    fn refuses_http_servers() {
+        let http = McpServer::Http {
+            url: "http://localhost/mcp".to_string(),
+            headers: None,
+        };
+        let coral_rs = CoralRs {
+            runtimes: Arc::default(),
+            mcps: Arc::new(McpServers {
+                servers: HashMap::from([("remote".to_string(), http)]),
+            }),
+        };
+        let e = coral_rs.render_servers("    ").unwrap_err();
+        assert!(
+            e.to_string().contains("'remote' uses the http transport"),
+            "{e}"
+        );
+        let contents = "    let mut agent = Agent::new(completion);\n\n";
+        assert!(coral_rs.template(contents).is_err());
+    }
 }
@@ -0,0 +1,464 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use itertools::Itertools;
+use minijinja::{Environment, Value, context};
+use serde::Serialize;
+
+use crate::Runtime;
+use crate::frameworks::Template;
+use crate::mcp_server::{McpServer, McpServers};
+use crate::options::InferredOption;
+use crate::template_manifest::TemplateManifest;
+use crate::templates::TemplateSource;
+
+/// A template that is rendered with MiniJinja as its [`TemplateManifest`] says, rather than by a
+/// framework's code - so a template only needs its manifest to work with coralizer.
+pub struct Declarative {
+    manifest: Arc<TemplateManifest>,
+    source: TemplateSource,
+    env: Environment<'static>,
+    context: Value,
+    /// Whether whole files are rendered, as when scaffolding - agents that were already rendered
+    /// only get MCP servers at their injection points
+    scaffolding: bool,
+}
+
+/// What a declarative template's files are rendered with
+#[derive(Debug, Serialize)]
+pub struct TemplateContext {
+    pub name: String,
+    pub description: String,
+    /// Sorted by name
+    pub servers: Vec<ServerContext>,
+    pub options: BTreeMap<String, OptionContext>,
+    pub runtimes: Vec<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServerContext {
+    pub name: String,
+    /// `stdio`, `sse` or `http`
+    pub transport: &'static str,
+    pub command: Option<String>,
+    pub args: Vec<String>,
+    /// Env vars by the agent option they're set to
+    pub env: BTreeMap<String, String>,
+    pub cwd: Option<String>,
+    pub env_file: Option<String>,
+    pub url: Option<String>,
+    /// Headers by the agent option they're set to
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OptionContext {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub default: Option<String>,
+    pub description: String,
+    pub secret: bool,
+}
+
+impl TemplateContext {
+    pub fn new(
+        name: &str,
+        description: &str,
+        mcps: &McpServers,
+        options: &BTreeMap<String, InferredOption>,
+        runtimes: &HashSet<Runtime>,
+    ) -> Self {
+        let servers = mcps
+            .servers
+            .iter()
+            .sorted_by_key(|(name, _)| *name)
+            .map(|(name, server)| ServerContext::new(name, server))
+            .collect();
+        let options = options
+            .iter()
+            .map(|(name, option)| {
+                let option = OptionContext {
+                    kind: option.kind.as_str(),
+                    default: option.default.clone(),
+                    description: option.description.clone(),
+                    secret: option.secret,
+                };
+                (name.clone(), option)
+            })
+            .collect();
+        let runtimes = runtimes
+            .iter()
+            .map(|runtime| match runtime {
+                Runtime::Npx => "npx",
+            })
+            .sorted()
+            .collect();
+        TemplateContext {
+            name: name.to_string(),
+            description: description.to_string(),
+            servers,
+            options,
+            runtimes,
+        }
+    }
+}
+
+impl ServerContext {
+    fn new(name: &str, server: &McpServer) -> Self {
+        let sorted = |map: &Option<_>| -> BTreeMap<String, String> {
+            map.iter()
+                .flatten()
+                .map(|(k, v): (&String, &String)| (k.clone(), v.clone()))
+                .collect()
+        };
+        let mut context = ServerContext {
+            name: name.to_string(),
+            transport: "stdio",
+            command: None,
+            args: vec![],
+            env: BTreeMap::new(),
+            cwd: None,
+            env_file: None,
+            url: None,
+            headers: BTreeMap::new(),
+        };
+        match server {
+            McpServer::Stdio {
+                command,
+                args,
+                env,
+                cwd,
+                env_file,
+            } => {
+                context.command = Some(command.clone());
+                context.args = args.clone();
+                context.env = sorted(env);
+                context.cwd = cwd.clone();
+                context.env_file = env_file.clone();
+            }
+            McpServer::Sse { url, headers } | McpServer::Http { url, headers } => {
+                context.transport = match server {
+                    McpServer::Sse { .. } => "sse",
+                    _ => "http",
+                };
+                context.url = Some(url.clone());
+                context.headers = sorted(headers);
+            }
+        }
+        context
+    }
+}
+
+impl Declarative {
+    pub fn new(
+        manifest: Arc<TemplateManifest>,
+        source: TemplateSource,
+        context: TemplateContext,
+        scaffolding: bool,
+    ) -> anyhow::Result<Self> {
+        let mut env = Environment::new();
+        env.set_keep_trailing_newline(true);
+        if let Some(server) = &manifest.server {
+            env.add_template_owned("server", server.clone())
+                .context("invalid `server` template")?;
+        }
+        Ok(Declarative {
+            manifest,
+            source,
+            env,
+            context: Value::from_serialize(&context),
+            scaffolding,
+        })
+    }
+
+    fn render(&self, contents: &str) -> anyhow::Result<String> {
+        Ok(self.env.render_str(contents, &self.context)?)
+    }
+}
+
+impl Template for Declarative {
+    fn name(&self) -> &'static str {
+        "declarative"
+    }
+    fn default_source(&self) -> TemplateSource {
+        self.source.clone()
+    }
+    fn include_file(&self, entry: &ignore::DirEntry) -> bool {
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        !self.manifest.excludes(entry.path(), is_dir)
+    }
+    fn is_templated_file(&self, path: &Path) -> bool {
+        (self.scaffolding && self.manifest.renders(path)) || self.manifest.is_templated_file(path)
+    }
+    fn template(&self, contents: &str) -> anyhow::Result<Option<String>> {
+        self.render(contents).map(Some)
+    }
+    fn render_servers(&self, indent: &str) -> anyhow::Result<String> {
+        let Some(template) = self.env.get_template("server").ok() else {
+            anyhow::bail!("the template manifest has no `server` template to inject servers with");
+        };
+        let mut code = String::new();
+        for server in self.context.get_attr("servers")?.try_iter()? {
+            let rendered = template.render(context! { server, ..self.context.clone() })?;
+            for line in rendered.trim_end().lines() {
+                code += &format!("{indent}{line}\n");
+            }
+        }
+        Ok(code)
+    }
+    /// Rendered servers aren't marked off from each other, so `remove-mcp` refuses these agents
+    fn remove_server(&self, _contents: &str, _name: &str) -> Option<(String, String)> {
+        None
+    }
+    /// Without markers, a server is found by its name as a string literal
+    fn has_server(&self, contents: &str, name: &str) -> bool {
+        ['"', '\'', '`']
+            .iter()
+            .any(|quote| contents.contains(&format!("{quote}{name}{quote}")))
+    }
+    fn server_options(&self, _source: &str) -> Vec<String> {
+        vec![]
+    }
+
+    fn templates_file(&self, _manifest: Option<&TemplateManifest>, rel_path: &Path) -> bool {
+        self.is_templated_file(rel_path)
+    }
+
+    fn inject(
+        &self,
+        _manifest: Option<&TemplateManifest>,
+        rel_path: &Path,
+        contents: &str,
+    ) -> anyhow::Result<String> {
+        // a rendered file has the servers it's rendered with - its injection points are for
+        // servers added later
+        if self.scaffolding && self.manifest.renders(rel_path) {
+            return self
+                .render(contents)
+                .map_err(|e| anyhow::anyhow!("couldn't render '{}' - {e}", rel_path.display()));
+        }
+        match self
+            .manifest
+            .inject(rel_path, contents, |indent| self.render_servers(indent))?
+        {
+            Some(contents) => Ok(contents),
+            None => anyhow::bail!(
+                "'{}' is missing {} - where its template manifest says the MCP servers go",
+                rel_path.display(),
+                self.manifest.describe_injection(rel_path)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+
+    use crate::agent_config::OptionType;
+
+    use super::*;
+
+    fn mcps() -> McpServers {
+        McpServers {
+            servers: HashMap::from([
+                (
+                    "fs".to_string(),
+                    McpServer::Stdio {
+                        command: "npx".to_string(),
+                        args: vec!["-y".to_string(), "server-fs".to_string()],
+                        env: Some(HashMap::from([("ROOT".to_string(), "FS_ROOT".to_string())])),
+                        cwd: Some("/srv".to_string()),
+                        env_file: Some(".env".to_string()),
+                    },
+                ),
+                (
+                    "events".to_string(),
+                    McpServer::Sse {
+                        url: "https://example.com/sse".to_string(),
+                        headers: Some(HashMap::from([(
+                            "Authorization".to_string(),
+                            "EVENTS_TOKEN".to_string(),
+                        )])),
+                    },
+                ),
+                (
+                    "api".to_string(),
+                    McpServer::Http {
+                        url: "https://example.com/mcp".to_string(),
+                        headers: None,
+                    },
+                ),
+            ]),
+        }
+    }
+
+    fn context() -> TemplateContext {
+        let options = BTreeMap::from([(
+            "FS_ROOT".to_string(),
+            InferredOption {
+                kind: OptionType::String,
+                default: Some("/data".to_string()),
+                description: "Root of the files".to_string(),
+                secret: false,
+            },
+        )]);
+        TemplateContext::new(
+            "my-agent",
+            "Reads files.",
+            &mcps(),
+            &options,
+            &HashSet::from([Runtime::Npx]),
+        )
+    }
+
+    /// A declarative template with the manifest `toml`, kept in the returned dir
+    fn template(toml: &str, scaffolding: bool) -> (tempfile::TempDir, anyhow::Result<Declarative>) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(TemplateManifest::FILE), toml).unwrap();
+        let manifest = TemplateManifest::load(dir.path()).unwrap().unwrap();
+        let source = TemplateSource::Dir {
+            path: dir.path().to_path_buf(),
+        };
+        let declarative = Declarative::new(Arc::new(manifest), source, context(), scaffolding);
+        (dir, declarative)
+    }
+
+    const MANIFEST: &str = r#"
+render = ["index.js"]
+server = """
+"{{ server.name }}": {
+  transport: {{ server.transport|tojson }},
+},"""
+
+[[inject]]
+file = "index.js"
+marker = "// coralizer: mcp servers"
+"#;
+
+    const INDEX: &str = "\
+const name = {{ name|tojson }};
+const servers = {
+{%- for server in servers %}
+  {{ server.name|tojson }}: {},
+{%- endfor %}
+  // coralizer: mcp servers
+};
+";
+
+    #[test]
+    fn describes_every_transport() {
+        let context = context();
+        let servers = context
+            .servers
+            .iter()
+            .map(|server| (server.name.as_str(), server.transport))
+            .collect_vec();
+        assert_eq!(
+            servers,
+            [("api", "http"), ("events", "sse"), ("fs", "stdio")]
+        );
+
+        let api = &context.servers[0];
+        assert_eq!(api.url.as_deref(), Some("https://example.com/mcp"));
+        assert!(api.headers.is_empty());
+        assert_eq!(api.command, None);
+
+        let events = &context.servers[1];
+        assert_eq!(events.url.as_deref(), Some("https://example.com/sse"));
+        assert_eq!(
+            events.headers,
+            BTreeMap::from([("Authorization".to_string(), "EVENTS_TOKEN".to_string())])
+        );
+
+        let fs = &context.servers[2];
+        assert_eq!(fs.command.as_deref(), Some("npx"));
+        assert_eq!(fs.args, ["-y", "server-fs"]);
+        assert_eq!(
+            fs.env,
+            BTreeMap::from([("ROOT".to_string(), "FS_ROOT".to_string())])
+        );
+        assert_eq!(fs.cwd.as_deref(), Some("/srv"));
+        assert_eq!(fs.env_file.as_deref(), Some(".env"));
+        assert_eq!(fs.url, None);
+
+        let root = &context.options["FS_ROOT"];
+        assert_eq!(root.kind, "string");
+        assert_eq!(root.default.as_deref(), Some("/data"));
+        assert_eq!(context.runtimes, ["npx"]);
+    }
+
+    #[test]
+    fn rejects_invalid_server_templates() {
+        let (_dir, declarative) = template("server = \"{{ server.name\"", true);
+        let e = declarative.err().unwrap();
+        assert!(e.to_string().contains("invalid `server` template"), "{e}");
+    }
+
+    #[test]
+    fn renders_servers_indented() {
+        let (_dir, declarative) = template(MANIFEST, false);
+        assert_eq!(
+            declarative.unwrap().render_servers("    ").unwrap(),
+            "    \"api\": {\n      transport: \"http\",\n    },\n    \
+             \"events\": {\n      transport: \"sse\",\n    },\n    \
+             \"fs\": {\n      transport: \"stdio\",\n    },\n"
+        );
+
+        let (_dir, declarative) = template("", false);
+        assert!(declarative.unwrap().render_servers("").is_err());
+    }
+
+    #[test]
+    fn renders_whole_files_when_scaffolding() {
+        let (_dir, declarative) = template(MANIFEST, true);
+        let declarative = declarative.unwrap();
+        let index = Path::new("index.js");
+        assert!(declarative.is_templated_file(index));
+        assert_eq!(
+            declarative.inject(None, index, INDEX).unwrap(),
+            "const name = \"my-agent\";\nconst servers = {\n  \"api\": {},\n  \"events\": {},\n  \
+             \"fs\": {},\n  // coralizer: mcp servers\n};\n"
+        );
+
+        let e = declarative.inject(None, index, "{{ name").unwrap_err();
+        assert!(e.to_string().contains("couldn't render 'index.js'"), "{e}");
+    }
+
+    #[test]
+    fn injects_into_existing_agents() {
+        let (_dir, declarative) = template(MANIFEST, false);
+        let declarative = declarative.unwrap();
+        let index = Path::new("index.js");
+        let existing =
+            "const name = {{ kept }};\nconst servers = {\n  // coralizer: mcp servers\n};\n";
+        let injected = declarative.inject(None, index, existing).unwrap();
+        assert!(
+            injected.starts_with("const name = {{ kept }};\nconst servers = {\n  \"api\": {\n")
+        );
+        assert!(injected.ends_with("  },\n  // coralizer: mcp servers\n};\n"));
+
+        let e = declarative
+            .inject(None, index, "const servers = {};\n")
+            .unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("missing '// coralizer: mcp servers'"),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn finds_servers_by_name() {
+        let (_dir, declarative) = template(MANIFEST, false);
+        let declarative = declarative.unwrap();
+        let contents = declarative.render_servers("  ").unwrap();
+        assert!(declarative.has_server(&contents, "fs"));
+        assert!(declarative.has_server("servers = { 'api': {} }", "api"));
+        assert!(!declarative.has_server(&contents, "f"));
+        assert!(!declarative.has_server("const fs = require('fs/promises');", "fs"));
+    }
+}
@@ -11,8 +11,9 @@ use toml_edit::{DocumentMut, Formatted};
 
 use crate::Runtime;
 use crate::edit::edit_file_str;
-use crate::frameworks::Template;
+use crate::frameworks::{Template, find_closing};
 use crate::mcp_server::{McpServer, McpServers};
+use crate::templates::TemplateSource;
 
 #[derive(Clone)]
 pub struct Langchain {
@@ -24,13 +25,16 @@ impl Template for Langchain {
     fn name(&self) -> &'static str {
         "langchain-agent"
     }
-    fn artifact(&self) -> (&'static str, &'static str) {
-        (
-            "https://github.com/Coral-Protocol/langchain-agent/archive/d77845581b94e17c39bfcf0f57c6faf89bdc90d2.zip",
-            "d77845581b94e17c39bfcf0f57c6faf89bdc90d2.zip",
-        )
+    fn default_source(&self) -> TemplateSource {
+        TemplateSource::Git {
+            git: "https://github.com/Coral-Protocol/langchain-agent".to_string(),
+            rev: "d77845581b94e17c39bfcf0f57c6faf89bdc90d2".to_string(),
+            // TODO: pin the archive of `rev` - until then, the SHA-256 recorded when it's first
+            // downloaded guards the cached copy
+            sha256: None,
+        }
     }
-    fn include_file(entry: &ignore::DirEntry) -> bool {
+    fn include_file(&self, entry: &ignore::DirEntry) -> bool {
         if entry
             .file_name()
             .to_str()
@@ -47,84 +51,85 @@ impl Template for Langchain {
         }
         false
     }
-    fn template(&self, contents: &str) -> String {
+    fn template(&self, contents: &str) -> anyhow::Result<Option<String>> {
         let mcp_client_re =
             Regex::new(r#"MultiServerMCPClient\s*\(\s*connections\s*=\s*\{\s*"coral"\s*:\s*\{(\s*".*,\n)*(\s*)}"#)
                 .unwrap();
+        let Some(caps) = mcp_client_re.captures(contents) else {
+            return Ok(None);
+        };
+        let group = caps.get(2).expect("group 2 to exist");
+        let ind = " ".repeat(group.len());
+        let followed_by_comma = contents[group.end() + 1..].trim_start().starts_with(',');
+        let servers = self.servers_code(&ind);
+        let servers = servers.trim_end();
+        // servers injected by an earlier run already follow with their own comma
+        let servers = match followed_by_comma {
+            true => servers.strip_suffix(',').unwrap_or(servers),
+            false => servers,
+        };
         let mut contents = contents.to_string();
-        if let Some(caps) = mcp_client_re.captures(&contents) {
-            let group = caps.get(2).expect("group 2 to exist");
-            let ind = " ".repeat(group.len());
-            let mut s = String::new();
-            writeln!(s, ",").unwrap();
-            for (i, (mcp_name, mcp)) in self.mcps.servers.iter().enumerate() {
-                // TODO (alan): dedupe this
-                match mcp {
-                    McpServer::Stdio { command, args, env } => {
-                        let args = args.iter().map(|a| format!("\"{a}\"")).collect_vec();
-                        writeln!(s, r#"{ind}"{mcp_name}": {{"#).unwrap();
-                        writeln!(s, r#"{ind}    "transport": "stdio","#).unwrap();
-                        writeln!(s, r#"{ind}    "command": "{command}","#).unwrap();
-                        if let Some(env) = env
-                            && !env.is_empty()
-                        {
-                            writeln!(s, r#"{ind}    "env": {{"#).unwrap();
-                            for (i, (env, opt)) in env.iter().enumerate() {
-                                write!(s, r#"{ind}        "{env}": asserted_env("{opt}")"#)
-                                    .unwrap();
-                                match i + 1 == env.len() {
-                                    true => writeln!(s, ","),
-                                    false => writeln!(s),
-                                }
-                                .unwrap()
-                            }
-                            writeln!(s, r#"{ind}    }},"#).unwrap();
-                        }
-                        writeln!(s, r#"{ind}    "args": [{}]"#, args.join(", ")).unwrap();
-                        write!(s, r#"{ind}}}"#).unwrap();
-                    }
-                    McpServer::Http { url, headers } | McpServer::Sse { url, headers } => {
-                        let transport = match mcp {
-                            McpServer::Http { .. } => "streamable_http",
-                            McpServer::Sse { .. } => "sse",
-                            _ => unreachable!(),
-                        };
-                        writeln!(s, r#"{ind}"{mcp_name}": {{"#).unwrap();
-                        writeln!(s, r#"{ind}    "transport": "{transport}","#).unwrap();
-                        write!(s, r#"{ind}    "url": "{url}""#).unwrap();
-                        if let Some(headers) = headers
-                            && !headers.is_empty()
-                        {
-                            writeln!(s, ",").unwrap();
-                            writeln!(s, r#"{ind}    "headers": {{"#).unwrap();
-                            for (i, (header, opt)) in headers.iter().enumerate() {
-                                write!(s, r#"{ind}        "{header}": asserted_env("{opt}")"#)
-                                    .unwrap();
-                                match i + 1 == header.len() {
-                                    true => writeln!(s, ","),
-                                    false => writeln!(s),
-                                }
-                                .unwrap()
-                            }
-                            write!(s, r#"{ind}    }}"#).unwrap();
-                        }
-                        writeln!(s).unwrap();
-                        write!(s, r#"{ind}}}"#).unwrap();
-                    }
-                }
-                match i + 1 == self.mcps.servers.len() {
-                    true => write!(s, ","),
-                    false => writeln!(s),
-                }
-                .unwrap()
-            }
-            contents.insert_str(group.end() + 1, &s);
-        } else {
-            panic!("bad");
+        contents.insert_str(group.end() + 1, &format!(",\n{servers}"));
+        Ok(Some(contents))
+    }
+
+    fn render_servers(&self, ind: &str) -> anyhow::Result<String> {
+        Ok(self.servers_code(ind))
+    }
+
+    fn finish_template(&self, mut contents: String) -> String {
+        if self.uses_env_files() && !contents.contains("import dotenv_values") {
+            let first_import = Regex::new(r"(?m)^(import|from) ").unwrap();
+            let at = first_import.find(&contents).map_or(0, |m| m.start());
+            contents.insert_str(at, "from dotenv import dotenv_values\n");
         }
         contents
     }
 
+    fn remove_server(&self, contents: &str, name: &str) -> Option<(String, String)> {
+        let client = contents.find("MultiServerMCPClient")?;
+        let server_re =
+            Regex::new(&format!(r#"["']{}["']\s*:\s*\{{"#, regex::escape(name))).unwrap();
+        // an entry whose braces don't balance is skipped, rather than hiding the ones after it
+        let (m, close) = server_re
+            .find_iter(contents)
+            .filter(|m| m.start() >= client)
+            .find_map(|m| Some((m, find_closing(contents, m.end() - 1, b"\"'")?)))?;
+
+        // remove the whole lines of the entry, along with the comma separating it from its siblings
+        let mut start = contents[..m.start()]
+            .rfind('\n')
+            .map_or(m.start(), |i| i + 1);
+        let mut end = close + 1;
+        let rest = &contents[end..];
+        if rest.trim_start().starts_with(',') {
+            end += rest.find(',').expect("comma") + 1;
+            let rest = &contents[end..];
+            if let Some(eol) = rest.find('\n')
+                && rest[..eol].trim().is_empty()
+            {
+                end += eol + 1;
+            }
+        } else if let Some(before) = contents[..start].trim_end().strip_suffix(',') {
+            start = before.len();
+            end = close + 1;
+        }
+
+        let removed = contents[m.start()..=close].to_string();
+        Some((
+            format!("{}{}", &contents[..start], &contents[end..]),
+            removed,
+        ))
+    }
+
+    fn server_options(&self, source: &str) -> Vec<String> {
+        let asserted_re = Regex::new(r#"asserted_env\(\s*"([^"]*)"\s*\)"#).unwrap();
+        asserted_re
+            .captures_iter(source)
+            .map(|caps| caps[1].to_string())
+            .collect()
+    }
+
     fn post_process(&self, root: &Path, agent_name: &str) -> std::io::Result<()> {
         println!("🔧 {:>18} fixup", style("'pyproject.toml'").blue());
         edit_file_str(root.join("pyproject.toml"), |contents| {
@@ -154,15 +159,137 @@ impl Template for Langchain {
             Ok::<_, io::Error>(pyproject.to_string())
         })?;
 
+        self.install_runtimes(root)?;
+        self.install_dependencies(root)
+    }
+
+    fn post_inject(&self, root: &Path) -> std::io::Result<()> {
+        self.install_runtimes(root)?;
+        self.install_dependencies(root)
+    }
+}
+
+/// A Python string literal - JSON's escapes are valid Python
+fn py_str(s: &str) -> String {
+    serde_json::to_string(s).expect("string to serialize")
+}
+
+impl Langchain {
+    /// The MCP servers' code, each line indented by `ind`
+    fn servers_code(&self, ind: &str) -> String {
+        let mut s = String::new();
+        for (mcp_name, mcp) in self.mcps.servers.iter() {
+            // TODO (alan): dedupe this
+            match mcp {
+                McpServer::Stdio {
+                    command,
+                    args,
+                    env,
+                    cwd,
+                    env_file,
+                } => {
+                    let args = args.iter().map(|a| py_str(a)).collect_vec();
+                    let env = env.iter().flatten().collect_vec();
+                    writeln!(s, r#"{ind}"{mcp_name}": {{"#).unwrap();
+                    writeln!(s, r#"{ind}    "transport": "stdio","#).unwrap();
+                    writeln!(s, r#"{ind}    "command": {},"#, py_str(command)).unwrap();
+                    if let Some(cwd) = cwd {
+                        writeln!(s, r#"{ind}    "cwd": {},"#, py_str(cwd)).unwrap();
+                    }
+                    if !env.is_empty() || env_file.is_some() {
+                        writeln!(s, r#"{ind}    "env": {{"#).unwrap();
+                        if let Some(env_file) = env_file {
+                            writeln!(s, r#"{ind}        **dotenv_values({}),"#, py_str(env_file))
+                                .unwrap();
+                        }
+                        for (env, opt) in env {
+                            writeln!(s, r#"{ind}        "{env}": asserted_env("{opt}"),"#).unwrap();
+                        }
+                        writeln!(s, r#"{ind}    }},"#).unwrap();
+                    }
+                    writeln!(s, r#"{ind}    "args": [{}]"#, args.join(", ")).unwrap();
+                }
+                McpServer::Http { url, headers } | McpServer::Sse { url, headers } => {
+                    let transport = match mcp {
+                        McpServer::Http { .. } => "streamable_http",
+                        McpServer::Sse { .. } => "sse",
+                        _ => unreachable!(),
+                    };
+                    writeln!(s, r#"{ind}"{mcp_name}": {{"#).unwrap();
+                    writeln!(s, r#"{ind}    "transport": "{transport}","#).unwrap();
+                    write!(s, r#"{ind}    "url": {}"#, py_str(url)).unwrap();
+                    if let Some(headers) = headers
+                        && !headers.is_empty()
+                    {
+                        writeln!(s, ",").unwrap();
+                        writeln!(s, r#"{ind}    "headers": {{"#).unwrap();
+                        for (header, opt) in headers {
+                            writeln!(s, r#"{ind}        "{header}": asserted_env("{opt}"),"#)
+                                .unwrap();
+                        }
+                        write!(s, r#"{ind}    }}"#).unwrap();
+                    }
+                    writeln!(s).unwrap();
+                }
+            }
+            writeln!(s, r#"{ind}}},"#).unwrap();
+        }
+        s
+    }
+
+    fn uses_env_files(&self) -> bool {
+        self.mcps.servers.values().any(|mcp| {
+            matches!(
+                mcp,
+                McpServer::Stdio {
+                    env_file: Some(_),
+                    ..
+                }
+            )
+        })
+    }
+
+    fn install_dependencies(&self, root: &Path) -> std::io::Result<()> {
+        if !self.uses_env_files() {
+            return Ok(());
+        }
+        edit_file_str(root.join("pyproject.toml"), |contents| {
+            let mut pyproject: DocumentMut = contents.parse().map_err(io::Error::other)?;
+            let Some(deps) = pyproject
+                .get_mut("project")
+                .and_then(|e| e.get_mut("dependencies"))
+                .and_then(|e| e.as_array_mut())
+            else {
+                return Err(io::Error::other(
+                    "No project.dependencies key found in pyproject.toml!",
+                ));
+            };
+            if !deps
+                .iter()
+                .filter_map(|d| d.as_str())
+                .any(|d| d.starts_with("python-dotenv"))
+            {
+                println!("🔧 {:>18} fixup", style("'pyproject.toml'").blue());
+                deps.push("python-dotenv");
+            }
+            Ok::<_, io::Error>(pyproject.to_string())
+        })
+    }
+
+    fn install_runtimes(&self, root: &Path) -> std::io::Result<()> {
         if self.runtimes.contains(&Runtime::Npx) {
-            println!("🔧 {:>18} fixup", style("'Dockerfile'").blue());
+            const NODEJS: &str = include_str!("./nodejs.Dockerfile");
             edit_file_str(root.join("Dockerfile"), |mut contents| {
+                if contents.contains(NODEJS) {
+                    return Ok(contents);
+                }
+                println!("🔧 {:>18} fixup", style("'Dockerfile'").blue());
                 const NEEDLE: &str = "COPY --from=builder --chown=app:app /app/ /app/";
                 let off = contents.find(NEEDLE).ok_or_else(|| {
                     io::Error::other("Could not find relevant line in Dockerfile")
                 })?;
 
-                contents.insert_str(off, include_str!("./nodejs.Dockerfile"));
+                contents.insert_str(off, NODEJS);
                 Ok::<_, io::Error>(contents)
             })?;
         }
@@ -170,3 +297,98 @@ impl Template for Langchain {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(servers: &[(&str, McpServer)]) -> (Langchain, String) {
+        let mcps = McpServers {
+            servers: servers
+                .iter()
+                .map(|(name, server)| (name.to_string(), server.clone()))
+                .collect(),
+        };
+        let langchain = Langchain {
+            runtimes: Arc::default(),
+            mcps: Arc::new(mcps),
+        };
+        let contents = format!(
+            "client = MultiServerMCPClient(\n    connections={{\n{}    }}\n)\n",
+            langchain.servers_code("        ")
+        );
+        (langchain, contents)
+    }
+
+    fn headers(headers: &[(&str, &str)]) -> McpServer {
+        McpServer::Http {
+            url: "https://example.com/mcp".to_string(),
+            headers: Some(
+                headers
+                    .iter()
+                    .map(|(header, opt)| (header.to_string(), opt.to_string()))
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn removed_server_options_come_from_its_headers() {
+        let (langchain, contents) = agent(&[
+            (
+                "a",
+                headers(&[("Authorization", "A_TOKEN"), ("X-Org", "ORG")]),
+            ),
+            ("b", headers(&[("X-Org", "ORG")])),
+        ]);
+        let (remaining, removed) = langchain.remove_server(&contents, "a").unwrap();
+
+        assert_eq!(
+            langchain
+                .server_options(&removed)
+                .into_iter()
+                .sorted()
+                .collect_vec(),
+            ["A_TOKEN", "ORG"]
+        );
+        assert_eq!(langchain.server_options(&remaining), ["ORG"]);
+        assert!(langchain.remove_server(&remaining, "a").is_none());
+    }
+
+    #[test]
+    fn skips_unbalanced_entries() {
+        let (langchain, contents) = agent(&[("a", headers(&[]))]);
+        // the quote never closes, so neither does the brace
+        let contents = contents.replace("connections={", "connections={\n        # \"a\": {'");
+        let (remaining, _) = langchain.remove_server(&contents, "a").unwrap();
+        assert!(remaining.contains("# \"a\": {'"));
+    }
+
+    #[test]
+    fn escapes_string_literals() {
+        let server = McpServer::Stdio {
+            command: r"C:\node".to_string(),
+            args: vec![r#"--greeting="hi""#.to_string()],
+            env: None,
+            cwd: None,
+            env_file: None,
+        };
+        let sse = McpServer::Sse {
+            url: r#"https://example.com/sse?q="x""#.to_string(),
+            headers: None,
+        };
+        let (langchain, contents) = agent(&[("a", server)]);
+        assert!(contents.contains(r#""command": "C:\\node","#), "{contents}");
+        assert!(
+            contents.contains(r#""args": ["--greeting=\"hi\""]"#),
+            "{contents}"
+        );
+        assert!(langchain.remove_server(&contents, "a").is_some());
+
+        let (_, contents) = agent(&[("b", sse)]);
+        assert!(
+            contents.contains(r#""url": "https://example.com/sse?q=\"x\"""#),
+            "{contents}"
+        );
+    }
+}
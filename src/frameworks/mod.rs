@@ -1,6 +1,12 @@
-use custom_derive::custom_derive;
-use enum_derive::*;
-use std::{fmt::Display, path::Path};
+use clap::builder::{PossibleValue, StringValueParser, TypedValueParser};
+use itertools::Itertools;
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fmt::Display,
+    path::Path,
+    sync::Arc,
+};
 
 mod langchain;
 pub use langchain::*;
@@ -8,30 +14,103 @@ pub use langchain::*;
 mod coral_rs;
 pub use coral_rs::*;
 
+mod declarative;
+pub use declarative::*;
+
+use crate::Runtime;
 use crate::languages::Language;
+use crate::mcp_server::McpServers;
+use crate::template_manifest::TemplateManifest;
+use crate::templates::{TemplateRegistry, TemplateSource};
 
-custom_derive! {
-    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, clap::ValueEnum)]
-    #[derive(IterVariants(Frameworks))]
-    pub enum Framework {
-        // Python
-        Langchain,
-        // Rust
-        CoralRs
-    }
+/// A framework agents are scaffolded with - built into coralizer, or registered in the template
+/// registry with a template that is rendered as its [`TemplateManifest`] says
+#[derive(Clone, Debug)]
+pub struct Framework {
+    id: String,
+    name: String,
+    language: Language,
+    kind: FrameworkKind,
+}
+
+#[derive(Clone, Debug)]
+enum FrameworkKind {
+    Langchain,
+    CoralRs,
+    Registered(TemplateSource),
 }
 
 impl Framework {
+    fn builtin(id: &str, name: &str, language: Language, kind: FrameworkKind) -> Self {
+        Framework {
+            id: id.to_string(),
+            name: name.to_string(),
+            language,
+            kind,
+        }
+    }
+    pub fn langchain() -> Self {
+        Self::builtin(
+            "langchain",
+            "Langchain",
+            Language::Python,
+            FrameworkKind::Langchain,
+        )
+    }
+    pub fn coral_rs() -> Self {
+        Self::builtin(
+            "coral-rs",
+            "coral-rs",
+            Language::Rust,
+            FrameworkKind::CoralRs,
+        )
+    }
+    /// How the framework is named on the command line and in the template registry
+    pub fn id(&self) -> &str {
+        &self.id
+    }
     pub fn name(&self) -> &str {
-        match self {
-            Framework::Langchain => "Langchain",
-            Framework::CoralRs => "coral-rs",
+        &self.name
+    }
+    pub fn language(&self) -> &Language {
+        &self.language
+    }
+    /// Guesses the built-in framework of an already scaffolded agent from its project files.
+    pub fn detect(root: &Path) -> Option<Framework> {
+        if root.join("Cargo.toml").is_file() {
+            Some(Framework::coral_rs())
+        } else if root.join("pyproject.toml").is_file() {
+            Some(Framework::langchain())
+        } else {
+            None
         }
     }
-    pub fn language(&self) -> Language {
-        match self {
-            Framework::Langchain => Language::Python,
-            Framework::CoralRs => Language::Rust,
+    /// Where the framework's template is fetched from unless the template registry or
+    /// `--template` say otherwise
+    pub fn default_source(&self) -> TemplateSource {
+        match &self.kind {
+            FrameworkKind::Registered(source) => source.clone(),
+            _ => {
+                let mcps = Arc::new(McpServers {
+                    servers: HashMap::new(),
+                });
+                self.templater(Arc::default(), mcps)
+                    .expect("built-in frameworks have a templater")
+                    .default_source()
+            }
+        }
+    }
+    /// The framework's code for its templates - `None` for registered frameworks, whose templates
+    /// are declarative
+    pub fn templater(
+        &self,
+        runtimes: Arc<HashSet<Runtime>>,
+        mcps: Arc<McpServers>,
+    ) -> Option<Arc<dyn Template>> {
+        match self.kind {
+            FrameworkKind::Langchain => Some(Arc::new(Langchain { runtimes, mcps })),
+            FrameworkKind::CoralRs => Some(Arc::new(CoralRs { runtimes, mcps })),
+            FrameworkKind::Registered(_) => None,
         }
     }
 }
@@ -42,14 +121,86 @@ impl Display for Framework {
     }
 }
 
+/// The built-in frameworks, and those registered in a [`TemplateRegistry`]
+pub struct FrameworkRegistry {
+    frameworks: Vec<Framework>,
+}
+
+impl FrameworkRegistry {
+    pub fn new(templates: &TemplateRegistry) -> anyhow::Result<Self> {
+        let mut frameworks = vec![Framework::langchain(), Framework::coral_rs()];
+        for (id, registered) in &templates.frameworks {
+            if frameworks.iter().any(|framework| framework.id() == id) {
+                anyhow::bail!(
+                    "the {id} framework is built in - its template is overridden with \
+                     [templates.{id}] rather than registered"
+                );
+            }
+            frameworks.push(Framework {
+                id: id.clone(),
+                name: registered.name.clone(),
+                language: Language::from(registered.language.as_str()),
+                kind: FrameworkKind::Registered(registered.source.clone()),
+            });
+        }
+        Ok(FrameworkRegistry { frameworks })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Framework> {
+        self.frameworks.iter()
+    }
+
+    /// The framework named `id` on the command line, case-insensitively
+    pub fn get(&self, id: &str) -> anyhow::Result<Framework> {
+        match self
+            .iter()
+            .find(|framework| framework.id().eq_ignore_ascii_case(id))
+        {
+            Some(framework) => Ok(framework.clone()),
+            None => anyhow::bail!(
+                "unknown framework '{id}' - expected one of {}",
+                self.iter().map(|framework| framework.id()).join(", ")
+            ),
+        }
+    }
+
+    /// Parses `--framework`, offering the ids of these frameworks in `--help` and shell completions
+    pub fn id_parser(&self) -> FrameworkIdParser {
+        FrameworkIdParser(self.iter().map(|framework| framework.id.clone()).collect())
+    }
+}
+
+/// Takes any framework id - those registered in a `--template-registry` are only known once it's
+/// loaded, and [`FrameworkRegistry::get`] checks it then - but lists the known ones
+#[derive(Clone)]
+pub struct FrameworkIdParser(Vec<String>);
+
+impl TypedValueParser for FrameworkIdParser {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &OsStr,
+    ) -> Result<String, clap::Error> {
+        StringValueParser::new().parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(
+            self.0.iter().map(|id| PossibleValue::new(id.clone())),
+        ))
+    }
+}
+
 pub trait Template: Send + Sync {
     fn name(&self) -> &'static str;
-    fn artifact(&self) -> (&'static str, &'static str);
+    /// Where the template is fetched from unless the template registry or `--template` say
+    /// otherwise
+    fn default_source(&self) -> TemplateSource;
 
-    fn include_file(entry: &ignore::DirEntry) -> bool
-    where
-        Self: Sized,
-    {
+    fn include_file(&self, entry: &ignore::DirEntry) -> bool {
         let _ = entry;
         true
     }
@@ -57,6 +208,181 @@ pub trait Template: Send + Sync {
         let _ = path;
         true
     }
-    fn template(&self, contents: &str) -> String;
-    fn post_process(&self, root: &Path, agent_name: &str) -> std::io::Result<()>;
+    /// Injects the MCP servers into `contents` of a templated file of a template without a
+    /// [`TemplateManifest`], finding where they go itself - or `None` if it can't.
+    fn template(&self, contents: &str) -> anyhow::Result<Option<String>>;
+    /// The MCP servers' code, each line indented by `indent` and ending in a newline, to go at an
+    /// injection point of a [`TemplateManifest`]
+    fn render_servers(&self, indent: &str) -> anyhow::Result<String>;
+    /// Fixes up a templated file once the MCP servers are in it, e.g. with imports they need
+    fn finish_template(&self, contents: String) -> String {
+        contents
+    }
+    /// Removes the MCP server `name` from templated `contents`, returning the new contents and the
+    /// removed source - or `None` if `contents` doesn't have that server.
+    fn remove_server(&self, contents: &str, name: &str) -> Option<(String, String)>;
+    /// Whether templated `contents` already have an MCP server named `name`
+    fn has_server(&self, contents: &str, name: &str) -> bool {
+        self.remove_server(contents, name).is_some()
+    }
+    /// The agent options that the servers in `source` - as the framework writes them - are given
+    /// as their env vars or headers
+    fn server_options(&self, source: &str) -> Vec<String>;
+    /// Fixes up a scaffolded agent of a template without a [`TemplateManifest`] - those with one
+    /// are post-processed as it says
+    fn post_process(&self, _root: &Path, _agent_name: &str) -> std::io::Result<()> {
+        Ok(())
+    }
+    /// Called after new MCP servers were templated into an existing agent (see `add-mcp`).
+    fn post_inject(&self, root: &Path) -> std::io::Result<()> {
+        let _ = root;
+        Ok(())
+    }
+
+    /// Whether the file at `rel_path` gets the MCP servers - as the template's manifest says, if
+    /// it has one
+    fn templates_file(&self, manifest: Option<&TemplateManifest>, rel_path: &Path) -> bool {
+        match manifest {
+            Some(manifest) => manifest.is_templated_file(rel_path),
+            None => self.is_templated_file(rel_path),
+        }
+    }
+
+    /// Injects the MCP servers into `contents` of the file at `rel_path`
+    fn inject(
+        &self,
+        manifest: Option<&TemplateManifest>,
+        rel_path: &Path,
+        contents: &str,
+    ) -> anyhow::Result<String> {
+        let injected = match manifest {
+            Some(manifest) => {
+                manifest.inject(rel_path, contents, |indent| self.render_servers(indent))?
+            }
+            None => self.template(contents)?,
+        };
+        match (injected, manifest) {
+            (Some(contents), _) => Ok(self.finish_template(contents)),
+            (None, Some(manifest)) => anyhow::bail!(
+                "'{}' is missing {} - where its template manifest says the MCP servers go",
+                rel_path.display(),
+                manifest.describe_injection(rel_path)
+            ),
+            (None, None) => anyhow::bail!(
+                "couldn't find where the MCP servers go in '{}' - the template may have changed, \
+                 a {} declaring it would fix that",
+                rel_path.display(),
+                TemplateManifest::FILE
+            ),
+        }
+    }
+}
+
+/// Finds the bracket closing the one at `open`, skipping over string literals delimited by any of
+/// `quotes`.
+fn find_closing(contents: &str, open: usize, quotes: &[u8]) -> Option<usize> {
+    let bytes = contents.as_bytes();
+    let (open_ch, close_ch) = match bytes[open] {
+        b'(' => (b'(', b')'),
+        b'{' => (b'{', b'}'),
+        b'[' => (b'[', b']'),
+        _ => return None,
+    };
+    let mut depth = 0;
+    let mut in_str: Option<u8> = None;
+    let mut escaped = false;
+    for (i, &b) in bytes.iter().enumerate().skip(open) {
+        match in_str {
+            Some(_) if escaped => escaped = false,
+            Some(_) if b == b'\\' => escaped = true,
+            Some(q) if b == q => in_str = None,
+            Some(_) => {}
+            None if quotes.contains(&b) => in_str = Some(b),
+            None if b == open_ch => depth += 1,
+            None if b == close_ch => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            None => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(toml: &str) -> anyhow::Result<FrameworkRegistry> {
+        FrameworkRegistry::new(&toml_edit::de::from_str(toml).unwrap())
+    }
+
+    const REGISTERED: &str = r#"
+        [frameworks.crewai]
+        name = "CrewAI"
+        language = "python"
+        path = "crewai-agent"
+
+        [frameworks.mastra]
+        name = "Mastra"
+        language = "TypeScript"
+        path = "mastra-agent"
+    "#;
+
+    #[test]
+    fn parses_registered_languages() {
+        let frameworks = registry(REGISTERED).unwrap();
+        let languages = frameworks
+            .iter()
+            .map(|framework| (framework.id(), framework.language().clone()))
+            .collect_vec();
+        assert_eq!(
+            languages,
+            [
+                ("langchain", Language::Python),
+                ("coral-rs", Language::Rust),
+                ("crewai", Language::Python),
+                ("mastra", Language::Other("TypeScript".to_string())),
+            ]
+        );
+        assert_eq!(
+            frameworks.get("mastra").unwrap().to_string(),
+            "Mastra (TypeScript)"
+        );
+    }
+
+    #[test]
+    fn finds_frameworks_by_id() {
+        let frameworks = registry(REGISTERED).unwrap();
+        assert_eq!(frameworks.get("CrewAI").unwrap().id(), "crewai");
+        let e = frameworks.get("autogen").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "unknown framework 'autogen' - expected one of langchain, coral-rs, crewai, mastra"
+        );
+        assert!(
+            registry("[frameworks.langchain]\nname = \"L\"\nlanguage = \"Python\"\npath = \".\"")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn offers_known_ids() {
+        let parser = registry(REGISTERED).unwrap().id_parser();
+        let ids = parser
+            .possible_values()
+            .unwrap()
+            .map(|value| value.get_name().to_string())
+            .collect_vec();
+        assert_eq!(ids, ["langchain", "coral-rs", "crewai", "mastra"]);
+
+        // registered in a --template-registry, so checked once that's loaded
+        let cmd = clap::Command::new("coralizer");
+        assert_eq!(
+            parser.parse_ref(&cmd, None, OsStr::new("autogen")).unwrap(),
+            "autogen"
+        );
+    }
 }
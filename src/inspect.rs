@@ -0,0 +1,160 @@
+use colored::Colorize as _;
+use itertools::Itertools;
+use rmcp::model::{Implementation, Prompt, Resource, ResourceTemplate, Tool};
+use serde::Serialize;
+
+use crate::mcp_client::make_client;
+use crate::mcp_env::McpEnv;
+use crate::mcp_server::McpServer;
+
+/// Everything an MCP server offers, as reported by the server itself
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Inspection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_info: Option<Implementation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    pub tools: Vec<Tool>,
+    pub resources: Vec<Resource>,
+    pub resource_templates: Vec<ResourceTemplate>,
+    pub prompts: Vec<Prompt>,
+    /// Why the server (or one of its listings) couldn't be inspected
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+/// Connects to `server` and lists everything it offers. Listings the server doesn't declare a
+/// capability for are skipped rather than requested.
+pub async fn inspect(server: &McpServer, env: &McpEnv) -> Inspection {
+    let mut inspection = Inspection::default();
+    let client = match make_client(server, env).await {
+        Ok(client) => client,
+        Err(e) => {
+            let error = format!("failed to connect - {e}");
+            inspection.errors.push(env.redact(&error));
+            return inspection;
+        }
+    };
+    let peer = client.peer();
+    let capabilities = peer.peer_info().map(|info| {
+        inspection.server_info = Some(info.server_info.clone());
+        inspection.instructions = info.instructions.clone();
+        info.capabilities.clone()
+    });
+    let has = |capability: fn(&rmcp::model::ServerCapabilities) -> bool| {
+        capabilities.as_ref().is_none_or(capability)
+    };
+
+    macro_rules! list {
+        ($field:ident, $what:literal, $list:expr) => {
+            match $list.await {
+                Ok(items) => inspection.$field = items,
+                Err(e) => inspection
+                    .errors
+                    .push(env.redact(&format!("failed to list {} - {e}", $what))),
+            }
+        };
+    }
+    if has(|c| c.tools.is_some()) {
+        list!(tools, "tools", peer.list_all_tools());
+    }
+    if has(|c| c.resources.is_some()) {
+        list!(resources, "resources", peer.list_all_resources());
+        list!(
+            resource_templates,
+            "resource templates",
+            peer.list_all_resource_templates()
+        );
+    }
+    if has(|c| c.prompts.is_some()) {
+        list!(prompts, "prompts", peer.list_all_prompts());
+    }
+
+    let _ = client.cancel().await;
+    inspection
+}
+
+impl Inspection {
+    /// Prints the inspection of the server `name` as tables
+    pub fn print(&self, name: &str) {
+        match &self.server_info {
+            Some(info) => println!("{} ({} v{})", name.blue().bold(), info.name, info.version),
+            None => println!("{}", name.blue().bold()),
+        }
+        for error in &self.errors {
+            println!("  {}", error.red());
+        }
+        if let Some(instructions) = &self.instructions {
+            println!("  {}", instructions.dimmed());
+        }
+
+        section("Tools", self.tools.len());
+        let width = max_len(self.tools.iter().map(|t| t.name.len()));
+        for tool in self.tools.iter().sorted_by(|a, b| a.name.cmp(&b.name)) {
+            row(width, &tool.name, first_line(tool.description.as_deref()));
+            println!(
+                "    {:width$}  {}",
+                "",
+                serde_json::to_string(&tool.input_schema)
+                    .unwrap_or_default()
+                    .dimmed()
+            );
+        }
+
+        section("Resources", self.resources.len());
+        let width = max_len(self.resources.iter().map(|r| r.uri.len()));
+        for resource in self.resources.iter().sorted_by(|a, b| a.uri.cmp(&b.uri)) {
+            let mime = resource
+                .mime_type
+                .as_deref()
+                .map(|m| format!(" ({m})"))
+                .unwrap_or_default();
+            row(width, &resource.uri, &format!("{}{mime}", resource.name));
+        }
+
+        section("Resource templates", self.resource_templates.len());
+        let width = max_len(self.resource_templates.iter().map(|r| r.uri_template.len()));
+        for template in self
+            .resource_templates
+            .iter()
+            .sorted_by(|a, b| a.uri_template.cmp(&b.uri_template))
+        {
+            row(width, &template.uri_template, &template.name);
+        }
+
+        section("Prompts", self.prompts.len());
+        let width = max_len(self.prompts.iter().map(|p| p.name.len()));
+        for prompt in self.prompts.iter().sorted_by(|a, b| a.name.cmp(&b.name)) {
+            let arguments = prompt
+                .arguments
+                .iter()
+                .flatten()
+                .map(|a| match a.required {
+                    Some(true) => format!("{}*", a.name),
+                    _ => a.name.clone(),
+                })
+                .join(", ");
+            let description = first_line(prompt.description.as_deref());
+            row(width, &prompt.name, &format!("{description} [{arguments}]"));
+        }
+    }
+}
+
+fn section(title: &str, count: usize) {
+    if count > 0 {
+        println!("  {}", format!("{title} ({count})").bold());
+    }
+}
+
+fn max_len(lengths: impl Iterator<Item = usize>) -> usize {
+    lengths.max().unwrap_or_default()
+}
+
+fn row(width: usize, key: &str, value: &str) {
+    println!("    {}  {value}", format!("{key:width$}").green());
+}
+
+fn first_line(text: Option<&str>) -> &str {
+    text.and_then(|t| t.lines().next()).unwrap_or_default()
+}
@@ -0,0 +1,196 @@
+use std::env;
+use std::fmt::Display;
+
+use rig::agent::AgentBuilder;
+use rig::client::CompletionClient as _;
+use rig::completion::{CompletionModel, Prompt as _};
+use rig::providers::{
+    anthropic, deepseek, gemini, groq, mistral, ollama, openai, openrouter, perplexity, together,
+    xai,
+};
+
+/// The LLM providers supported by `rig`, used to write agent descriptions
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LlmProvider {
+    Openai,
+    /// Any server with an OpenAI-style chat completions API (vLLM, LM Studio, llama.cpp, ...)
+    OpenaiCompatible,
+    Anthropic,
+    Ollama,
+    Gemini,
+    Groq,
+    Mistral,
+    Deepseek,
+    Openrouter,
+    Xai,
+    Perplexity,
+    Together,
+}
+
+impl Display for LlmProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum as _;
+        f.write_str(
+            self.to_possible_value()
+                .expect("no skipped variants")
+                .get_name(),
+        )
+    }
+}
+
+impl LlmProvider {
+    /// Env var the provider's API key is read from
+    pub fn api_key_env(&self) -> Option<&'static str> {
+        Some(match self {
+            LlmProvider::Openai | LlmProvider::OpenaiCompatible => "OPENAI_API_KEY",
+            LlmProvider::Anthropic => "ANTHROPIC_API_KEY",
+            LlmProvider::Ollama => return None,
+            LlmProvider::Gemini => "GEMINI_API_KEY",
+            LlmProvider::Groq => "GROQ_API_KEY",
+            LlmProvider::Mistral => "MISTRAL_API_KEY",
+            LlmProvider::Deepseek => "DEEPSEEK_API_KEY",
+            LlmProvider::Openrouter => "OPENROUTER_API_KEY",
+            LlmProvider::Xai => "XAI_API_KEY",
+            LlmProvider::Perplexity => "PERPLEXITY_API_KEY",
+            LlmProvider::Together => "TOGETHER_API_KEY",
+        })
+    }
+
+    /// Env var the provider's API URL can be overridden with, as read by `rig`
+    fn base_url_env(&self) -> Option<&'static str> {
+        match self {
+            LlmProvider::Openai | LlmProvider::OpenaiCompatible => Some("OPENAI_BASE_URL"),
+            LlmProvider::Ollama => Some("OLLAMA_API_BASE_URL"),
+            _ => None,
+        }
+    }
+
+    /// Model used when none is given - there is no sensible one for an OpenAI-compatible server
+    pub fn default_model(&self) -> Option<&'static str> {
+        Some(match self {
+            LlmProvider::Openai => "gpt-4",
+            LlmProvider::OpenaiCompatible => return None,
+            LlmProvider::Anthropic => "claude-sonnet-4-5",
+            LlmProvider::Ollama => "llama3.2",
+            LlmProvider::Gemini => "gemini-2.5-flash",
+            LlmProvider::Groq => "llama-3.3-70b-versatile",
+            LlmProvider::Mistral => "mistral-large-latest",
+            LlmProvider::Deepseek => "deepseek-chat",
+            LlmProvider::Openrouter => "openai/gpt-4o-mini",
+            LlmProvider::Xai => "grok-3-mini",
+            LlmProvider::Perplexity => "sonar",
+            LlmProvider::Together => "meta-llama/Llama-3.3-70B-Instruct-Turbo",
+        })
+    }
+}
+
+/// Which LLM writes the agent description, from `--llm-*` flags or the answers file.
+#[derive(Debug, Clone, Default)]
+pub struct LlmConfig {
+    pub provider: Option<LlmProvider>,
+    pub model: Option<String>,
+    /// Overrides the provider's API URL - required for [`LlmProvider::OpenaiCompatible`]
+    pub base_url: Option<String>,
+    /// Never call an LLM, summarizing the tools offline instead (as does setting `SKIP_LLM`)
+    pub disabled: bool,
+}
+
+impl LlmConfig {
+    pub fn provider(&self) -> LlmProvider {
+        self.provider.unwrap_or(LlmProvider::Openai)
+    }
+
+    pub fn model(&self) -> anyhow::Result<String> {
+        let provider = self.provider();
+        self.model
+            .clone()
+            .or_else(|| provider.default_model().map(str::to_string))
+            .ok_or_else(|| anyhow::anyhow!("no model given for {provider} - pass `--llm-model`"))
+    }
+
+    /// Prompts the configured model, failing if it isn't set up (e.g. a missing API key) rather
+    /// than panicking like `rig`'s `from_env` does.
+    pub async fn prompt(&self, preamble: &str, prompt: &str) -> anyhow::Result<String> {
+        let provider = self.provider();
+        let model = self.model()?;
+        let key = match provider.api_key_env() {
+            Some(var) => env::var(var).ok(),
+            None => None,
+        };
+        let api_key = || {
+            key.clone().ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{} is not set",
+                    provider.api_key_env().expect("provider with an API key")
+                )
+            })
+        };
+        let base_url = self
+            .base_url
+            .clone()
+            .or_else(|| provider.base_url_env().and_then(|var| env::var(var).ok()));
+        let base_url = base_url.as_deref();
+
+        macro_rules! client {
+            ($provider:ident) => {{
+                let api_key = api_key()?;
+                let builder = $provider::Client::builder(&api_key);
+                match base_url {
+                    Some(url) => builder.base_url(url).build(),
+                    None => builder.build(),
+                }
+            }};
+        }
+
+        macro_rules! complete {
+            ($client:expr) => {
+                complete($client.completion_model(&model), preamble, prompt).await
+            };
+        }
+
+        match provider {
+            LlmProvider::Openai => complete!(client!(openai)),
+            LlmProvider::OpenaiCompatible => {
+                let base_url = base_url.ok_or_else(|| {
+                    anyhow::anyhow!("no base URL given for {provider} - pass `--llm-base-url`")
+                })?;
+                // local servers rarely need a key, but the client always sends one
+                let api_key = key.unwrap_or_else(|| "none".to_string());
+                let client = openai::Client::builder(&api_key).base_url(base_url).build();
+                // local servers only speak the older chat completions API, not OpenAI's responses
+                let model = client.completion_model(&model).completions_api();
+                complete(model, preamble, prompt).await
+            }
+            LlmProvider::Anthropic => complete!(client!(anthropic)?),
+            LlmProvider::Ollama => {
+                let builder = ollama::Client::builder();
+                complete!(match base_url {
+                    Some(url) => builder.base_url(url).build(),
+                    None => builder.build(),
+                })
+            }
+            LlmProvider::Gemini => complete!(client!(gemini)?),
+            LlmProvider::Groq => complete!(client!(groq)),
+            LlmProvider::Mistral => complete!(client!(mistral)),
+            LlmProvider::Deepseek => complete!(client!(deepseek)),
+            LlmProvider::Openrouter => complete!(client!(openrouter)),
+            LlmProvider::Xai => complete!(client!(xai)),
+            LlmProvider::Perplexity => complete!(client!(perplexity)),
+            LlmProvider::Together => complete!(client!(together)),
+        }
+    }
+}
+
+async fn complete<M: CompletionModel>(
+    model: M,
+    preamble: &str,
+    prompt: &str,
+) -> anyhow::Result<String> {
+    Ok(AgentBuilder::new(model)
+        .preamble(preamble)
+        // anthropic requires a limit
+        .max_tokens(1024)
+        .build()
+        .prompt(prompt)
+        .await?)
+}
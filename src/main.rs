@@ -1,81 +1,337 @@
-use clap::Parser;
+use clap::{CommandFactory as _, FromArgMatches as _, Parser, ValueEnum as _};
 use console::style;
-use futures_util::StreamExt as _;
 use ignore::{WalkBuilder, WalkState};
-use indicatif::ProgressBar;
-use inquire::{error::InquireResult, validator::ValueRequiredValidator};
+use indicatif::{HumanBytes, ProgressBar};
+use inquire::{
+    InquireError,
+    error::InquireResult,
+    validator::{Validation, ValueRequiredValidator},
+};
 use itertools::Itertools;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     ffi::OsStr,
     fmt::Display,
-    fs::{self, File},
+    fs,
     hash::Hash,
-    io::{self, Write},
+    io,
     path::PathBuf,
     sync::{Arc, Mutex},
     time::Duration,
 };
-use toml_edit::{DocumentMut, table, value};
-use zip::read::root_dir_common_filter;
 use colored::Colorize;
 use semver::Version;
 
-use crate::{edit::edit_file_str, frameworks::CoralRs, mcp_server::McpServers};
+use crate::{answers::Answers, edit::edit_file_str, mcp_server::McpServers};
 use crate::{
-    frameworks::{Framework, Langchain, Template},
+    agent_config::DockerRuntime,
+    llm::{LlmConfig, LlmProvider},
+    frameworks::{Declarative, Framework, FrameworkRegistry, Template, TemplateContext},
+    manifest::{AgentManifest, AgentRuntime},
+    mcp_env::McpEnv,
     mcp_server::McpServer,
+    oauth::CredentialStore,
+    template_manifest::TemplateManifest,
+    templates::{TemplateBundle, TemplateCache, TemplateRegistry, TemplateSource},
+    tool_cache::ToolCache,
+    options::infer_options,
 };
 use custom_derive::custom_derive;
 use enum_derive::*;
 
 #[derive(Parser)]
 pub enum Cli {
-    Mcp(McpParams),
+    Mcp(Box<McpParams>),
+    AddMcp(AddMcpParams),
+    RemoveMcp(RemoveMcpParams),
     Link(LinkParams),
     Unlink(LinkParams),
     Updeletelink(LinkParams),
+    Validate(ValidateParams),
+    Tools(ToolsParams),
+    Inspect(InspectParams),
+    Call(CallParams),
+    Login(LoginParams),
+    Logout(LogoutParams),
+    Cache(CacheParams),
+    Templates(TemplatesParams),
 }
 #[derive(clap::Args)]
 pub struct LinkParams {
     pub path: PathBuf,
 }
 #[derive(clap::Args)]
+pub struct AddMcpParams {
+    /// Root directory of an already scaffolded agent
+    pub path: PathBuf,
+
+    #[arg(long, short)]
+    pub name: Option<String>,
+    #[arg(long, short)]
+    pub kind: Option<McpKind>,
+}
+#[derive(clap::Args)]
+pub struct RemoveMcpParams {
+    /// Root directory of an already scaffolded agent
+    pub path: PathBuf,
+    /// Name of the MCP server to remove
+    pub name: String,
+}
+#[derive(clap::Args)]
+pub struct ToolsParams {
+    /// MCP config as JSON(C), TOML or YAML - or `-` to read it from stdin
+    pub mcp_servers_path: PathBuf,
+    /// List the tools live, replacing any cached listing
+    #[arg(long)]
+    pub refresh_tools: bool,
+    /// Seconds each server gets to start and list its tools
+    #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+    pub mcp_timeout: u64,
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+    #[command(flatten)]
+    pub mcp_env: McpEnvParams,
+}
+#[derive(clap::Args)]
+pub struct InspectParams {
+    /// MCP config as JSON(C), TOML or YAML - or `-` to read it from stdin
+    pub mcp_servers_path: PathBuf,
+    /// Print the inspection as JSON instead of tables
+    #[arg(long)]
+    pub json: bool,
+    #[command(flatten)]
+    pub mcp_env: McpEnvParams,
+}
+#[derive(clap::Args)]
+pub struct CallParams {
+    /// MCP config as JSON(C), TOML or YAML - or `-` to read it from stdin
+    pub mcp_servers_path: PathBuf,
+    /// Name of the server in the config to call tools of
+    pub server: String,
+    #[command(flatten)]
+    pub mcp_env: McpEnvParams,
+}
+#[derive(clap::Args)]
+pub struct LoginParams {
+    /// MCP config as JSON(C), TOML or YAML - or `-` to read it from stdin
+    pub mcp_servers_path: PathBuf,
+    /// Name of the remote server in the config to authorize with
+    pub server: String,
+    /// Client ID registered with the authorization server, for servers that don't support
+    /// dynamic client registration
+    #[arg(long)]
+    pub client_id: Option<String>,
+    /// Print the authorization URL without opening a browser
+    #[arg(long)]
+    pub no_browser: bool,
+}
+#[derive(clap::Args)]
+pub struct LogoutParams {
+    /// MCP config as JSON(C), TOML or YAML - or `-` to read it from stdin
+    pub mcp_servers_path: PathBuf,
+    /// Name of the remote server in the config to forget the tokens of
+    pub server: String,
+}
+#[derive(clap::Args)]
+pub struct CacheParams {
+    #[command(subcommand)]
+    pub command: CacheCommand,
+}
+#[derive(clap::Subcommand)]
+pub enum CacheCommand {
+    /// List cached template archives and extracted templates with their sizes
+    List {
+        #[arg(long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+        /// TOML index of template sources by framework [default: templates.toml in the config dir]
+        #[arg(long, value_name = "PATH|URL")]
+        template_registry: Option<String>,
+    },
+    /// Remove cached templates that no framework's template uses anymore
+    Prune {
+        /// TOML index of template sources by framework [default: templates.toml in the config dir]
+        #[arg(long, value_name = "PATH|URL")]
+        template_registry: Option<String>,
+    },
+    /// Remove every cached template
+    Clear,
+}
+#[derive(clap::Args)]
+pub struct TemplatesParams {
+    #[command(subcommand)]
+    pub command: TemplatesCommand,
+}
+#[derive(clap::Subcommand)]
+pub enum TemplatesCommand {
+    /// Bundle every framework's template archive into a directory, for machines without internet
+    Export {
+        /// Directory to write the archives and their index to
+        dir: PathBuf,
+        /// TOML index of template sources by framework [default: templates.toml in the config dir]
+        #[arg(long, value_name = "PATH|URL")]
+        template_registry: Option<String>,
+    },
+    /// Cache the templates of a bundle made by `templates export`, so `mcp --offline` finds them
+    Import {
+        /// Directory `templates export` wrote to
+        dir: PathBuf,
+    },
+}
+/// Real values for the MCP servers' env vars and headers, so they can be started to list tools
+#[derive(clap::Args)]
+pub struct McpEnvParams {
+    /// Value of an env var or header of the servers (repeatable)
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = mcp_env::parse_env_flag)]
+    pub vars: Vec<(String, String)>,
+    /// Dotenv file to read values from [default: .env]
+    #[arg(long)]
+    pub env_file: Option<PathBuf>,
+}
+impl McpEnvParams {
+    /// Must be called before [`McpServers::bind_options`]
+    fn load(&self, mcp_servers: &McpServers) -> anyhow::Result<McpEnv> {
+        McpEnv::new(mcp_servers, &self.vars, self.env_file.as_deref())
+    }
+}
+#[derive(clap::Args)]
+pub struct ValidateParams {
+    /// Root directory of an agent, or its coral-agent.toml
+    pub path: PathBuf,
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+    /// Fail on warnings too
+    #[arg(long)]
+    pub deny_warnings: bool,
+}
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+#[derive(clap::Args)]
 pub struct McpParams {
     pub path: PathBuf,
+    /// MCP config as JSON(C), TOML or YAML - or `-` to read it from stdin
     pub mcp_servers_path: PathBuf,
 
+    /// Framework to scaffold with - built in, or registered in the template registry
     #[arg(long, short)]
-    pub framework: Option<Framework>,
+    pub framework: Option<String>,
+    /// Template to scaffold from instead of the framework's: a directory or zip archive, a zip
+    /// or git repository URL (with an optional `#<rev>`), or a git ref of the framework's template
+    #[arg(long, value_name = "PATH|URL|REF")]
+    pub template: Option<String>,
+    /// TOML index of template sources by framework [default: templates.toml in the config dir]
+    #[arg(long, value_name = "PATH|URL")]
+    pub template_registry: Option<String>,
     #[arg(long, short)]
     pub name: Option<String>,
+
+    /// Never prompt - fail instead if an answer is missing
+    #[arg(long, short)]
+    pub yes: bool,
+    /// Replace the output directory if it already exists
+    #[arg(long)]
+    pub overwrite: bool,
+    /// Name of the Docker image written to `runtimes.docker.image`
+    #[arg(long)]
+    pub docker_image: Option<String>,
+    /// TOML file pre-filling the answers to every prompt
+    #[arg(long)]
+    pub answers: Option<PathBuf>,
+    /// List the servers' tools live, replacing any cached listing
+    #[arg(long)]
+    pub refresh_tools: bool,
+    /// Seconds each server gets to start and list its tools
+    #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+    pub mcp_timeout: u64,
+    /// Description of the agent - otherwise one is generated from its tools
+    #[arg(long)]
+    pub description: Option<String>,
+    /// Summarize the tools into a description offline instead of asking an LLM
+    #[arg(long)]
+    pub no_llm: bool,
+    /// Never download templates or call an LLM - the template has to be cached, like by
+    /// `coralizer templates import`
+    #[arg(long)]
+    pub offline: bool,
+    /// Give the agent an `Authorization` option for remote servers authorized with
+    /// `coralizer login`, to be sent as their `Authorization` header
+    #[arg(long)]
+    pub auth_options: bool,
+    /// LLM provider used to write the agent's description [default: openai]
+    #[arg(long)]
+    pub llm_provider: Option<LlmProvider>,
+    /// Model of the LLM provider, e.g. `gpt-4o` or `llama3.2`
+    #[arg(long)]
+    pub llm_model: Option<String>,
+    /// API URL of the LLM provider - required for `openai-compatible`
+    #[arg(long)]
+    pub llm_base_url: Option<String>,
+    #[command(flatten)]
+    pub mcp_env: McpEnvParams,
 }
 
+pub mod answers;
+pub mod call;
 pub mod edit;
 pub mod frameworks;
+pub mod inspect;
 pub mod mcp_client;
+pub mod mcp_env;
 pub mod mcp_server;
+pub mod oauth;
 pub mod agent_config;
+pub mod manifest;
+pub mod options;
+pub mod validate;
+
+pub mod llm;
+pub mod summary;
+pub mod template_manifest;
+pub mod templates;
+pub mod tool_cache;
 
 pub mod languages {
-    use custom_derive::custom_derive;
-    use enum_derive::*;
+    use std::fmt::Display;
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum Language {
+        Python,
+        Rust,
+        /// Whatever else a registered framework's template is written in
+        Other(String),
+    }
+
+    impl From<&str> for Language {
+        fn from(name: &str) -> Self {
+            match name.to_ascii_lowercase().as_str() {
+                "python" => Language::Python,
+                "rust" => Language::Rust,
+                _ => Language::Other(name.to_string()),
+            }
+        }
+    }
 
-    custom_derive! {
-        #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, EnumDisplay, IterVariants(Languages))]
-        pub enum Language {
-            Python,
-            Rust,
+    impl Display for Language {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(match self {
+                Language::Python => "Python",
+                Language::Rust => "Rust",
+                Language::Other(name) => name,
+            })
         }
     }
 }
 
 custom_derive! {
-    #[derive(Debug, IterVariants(TransportKind))]
+    #[derive(Clone, Copy, Debug, clap::ValueEnum)]
+    #[derive(IterVariants(TransportKind))]
     pub enum McpKind {
         Npx,
         Stdio,
         Sse,
+        Http,
     }
 }
 
@@ -85,6 +341,7 @@ impl Display for McpKind {
             McpKind::Npx => "npx",
             McpKind::Stdio => "stdio",
             McpKind::Sse => "sse",
+            McpKind::Http => "http",
         })
     }
 }
@@ -97,27 +354,35 @@ pub enum Runtime {
 
 impl McpKind {
     fn env_wizard() -> InquireResult<HashMap<String, String>> {
-        let mut envs = HashMap::new();
+        Self::option_wizard("Environment variable", "env var")
+    }
+    fn headers_wizard() -> InquireResult<HashMap<String, String>> {
+        Self::option_wizard("Header", "header")
+    }
+    /// Asks for names (of env vars or headers) and the agent option each one is mapped to.
+    fn option_wizard(kind: &str, short: &str) -> InquireResult<HashMap<String, String>> {
+        let mut map = HashMap::new();
         loop {
-            let env_name = inquire::Text::new(
-                "Environment variable name (leave blank to stop adding env vars)",
-            )
+            let name = inquire::Text::new(&format!(
+                "{kind} name (leave blank to stop adding {short}s)"
+            ))
             .prompt()?;
-            if env_name.is_empty() {
+            if name.is_empty() {
                 break;
             }
-            let opt_name = inquire::Text::new("Name of the agent option for this env var")
-                .with_placeholder(&env_name)
-                .prompt()?;
+            let opt_name =
+                inquire::Text::new(&format!("Name of the agent option for this {short}"))
+                    .with_placeholder(&name)
+                    .prompt()?;
 
             let opt_name = match opt_name.is_empty() {
-                true => env_name.clone(),
+                true => name.clone(),
                 false => opt_name,
             };
 
-            envs.insert(env_name, opt_name);
+            map.insert(name, opt_name);
         }
-        Ok(envs)
+        Ok(map)
     }
     pub fn wizard(self) -> InquireResult<McpServer> {
         Ok(match self {
@@ -138,6 +403,8 @@ impl McpKind {
                     command: "npx".to_string(),
                     args,
                     env: Some(envs),
+                    cwd: None,
+                    env_file: None,
                 }
             }
             McpKind::Stdio => {
@@ -151,22 +418,96 @@ impl McpKind {
 
                 let env = Some(Self::env_wizard()?);
 
-                McpServer::Stdio { command, args, env }
+                McpServer::Stdio {
+                    command,
+                    args,
+                    env,
+                    cwd: None,
+                    env_file: None,
+                }
+            }
+            McpKind::Sse | McpKind::Http => {
+                let url = inquire::Text::new("URL of the MCP server")
+                    .with_validator(|url: &str| {
+                        Ok(match reqwest::Url::parse(url) {
+                            Ok(url) if matches!(url.scheme(), "http" | "https") => {
+                                Validation::Valid
+                            }
+                            Ok(_) => Validation::Invalid("URL must be http(s)".into()),
+                            Err(e) => Validation::Invalid(e.into()),
+                        })
+                    })
+                    .prompt()?;
+
+                let headers = Some(Self::headers_wizard()?);
+
+                match self {
+                    McpKind::Sse => McpServer::Sse { url, headers },
+                    _ => McpServer::Http { url, headers },
+                }
             }
-            McpKind::Sse => todo!(),
         })
     }
 }
 
 async fn mcp_wizard(params: McpParams) -> InquireResult<()> {
+    let mut answers = Answers::load(params.answers.as_deref(), params.yes)?;
+    if params.overwrite {
+        answers.overwrite = Some(true);
+    }
+    if let Some(image) = params.docker_image {
+        answers.docker_image = Some(image);
+    }
+
+    let registry = TemplateRegistry::load(params.template_registry.as_deref(), params.offline)
+        .await
+        .map_err(|e| InquireError::Custom(e.into()))?;
+    let frameworks =
+        FrameworkRegistry::new(&registry).map_err(|e| InquireError::Custom(e.into()))?;
+    let framework = match (&params.framework, &answers.framework) {
+        (Some(id), _) => frameworks
+            .get(id)
+            .map_err(|e| InquireError::Custom(e.into()))?,
+        (None, Some(name)) => frameworks.get(name).map_err(|e| {
+            InquireError::Custom(format!("invalid framework in answers file - {e}").into())
+        })?,
+        (None, None) => answers.answer_or_prompt(None, "framework", || {
+            inquire::Select::new(
+                "Choose a framework",
+                frameworks
+                    .iter()
+                    .cloned()
+                    .sorted_by(|a, b| a.language().cmp(b.language()))
+                    .collect::<Vec<_>>(),
+            )
+            .prompt()
+        })?,
+    };
+
+    // fetched before anything else is asked, so a template that can't be had - like one that
+    // isn't cached `--offline` - is reported straight away
+    let default_source = framework.default_source();
+    let source = match &params.template {
+        Some(template) => TemplateSource::parse(template, &default_source)
+            .map_err(|e| InquireError::Custom(e.into()))?,
+        None => registry.source(framework.id(), default_source),
+    };
+    let cache = TemplateCache::in_cache_dir().map_err(|e| InquireError::Custom(e.into()))?;
+    let extracted_path = source
+        .fetch(&cache, params.offline)
+        .await
+        .map_err(|e| InquireError::Custom(format!("couldn't fetch the template - {e}").into()))?;
+
     if fs::exists(&params.path).unwrap() {
-        if !inquire::Confirm::new(&format!(
-            "Directory {} already exists - continue & delete existing?",
-            format!("'{}'", params.path.as_path().display()).blue()
-        ))
-        .with_default(false)
-        .prompt()?
-        {
+        let overwrite = answers.answer_or_prompt(answers.overwrite, "overwrite", || {
+            inquire::Confirm::new(&format!(
+                "Directory {} already exists - continue & delete existing?",
+                format!("'{}'", params.path.as_path().display()).blue()
+            ))
+            .with_default(false)
+            .prompt()
+        })?;
+        if !overwrite {
             println!("{} {}", ">".green(), "Cancelled.".red());
             return Ok(());
         }
@@ -174,7 +515,20 @@ async fn mcp_wizard(params: McpParams) -> InquireResult<()> {
         fs::create_dir_all(&params.path)?;
     }
 
-    let agent_name = params.name.unwrap_or_else(|| {
+    let llm = LlmConfig {
+        provider: match (params.llm_provider, &answers.llm_provider) {
+            (Some(provider), _) => Some(provider),
+            (None, Some(name)) => Some(LlmProvider::from_str(name, true).map_err(|e| {
+                InquireError::Custom(format!("invalid llm_provider in answers file - {e}").into())
+            })?),
+            (None, None) => None,
+        },
+        model: params.llm_model.or(answers.llm_model.clone()),
+        base_url: params.llm_base_url.or(answers.llm_base_url.clone()),
+        disabled: params.no_llm || params.offline,
+    };
+
+    let agent_name = params.name.or(answers.name.clone()).unwrap_or_else(|| {
         params
             .path
             .canonicalize()
@@ -186,39 +540,47 @@ async fn mcp_wizard(params: McpParams) -> InquireResult<()> {
             .to_string()
     });
 
-    let framework = match params.framework {
-        Some(framework) => framework,
-        None => inquire::Select::new(
-            "Choose a framework",
-            Framework::iter_variants()
-                .sorted_by_key(|f| f.language())
-                .collect::<Vec<_>>(),
-        )
-        .prompt()?,
-    };
-
-    let mut mcp_servers: McpServers = serde_json::from_str(
-        fs::read_to_string(params.mcp_servers_path)
-            .unwrap()
-            .as_str(),
-    )
-    .expect("invalid json");
-    mcp_servers
-        .servers
-        .values_mut()
-        .flat_map(|server| {
-            let map = match server {
-                McpServer::Http { headers, .. } | McpServer::Sse { headers, .. } => headers,
-                McpServer::Stdio { env, .. } => env,
-            };
-            map.iter_mut().flat_map(|env| env.iter_mut())
-        })
-        .for_each(|(k, v)| *v = k.clone());
-
+    let mut mcp_servers =
+        McpServers::read(&params.mcp_servers_path).map_err(|e| InquireError::Custom(e.into()))?;
+    let env = params
+        .mcp_env
+        .load(&mcp_servers)
+        .map_err(|e| InquireError::Custom(e.into()))?;
+    if params.auth_options {
+        let store = CredentialStore::in_config_dir().map_err(|e| InquireError::Custom(e.into()))?;
+        mcp_servers.add_oauth_headers(&store);
+    }
+    let defaults = mcp_servers.bind_options();
+
+    let cache = ToolCache::new(params.refresh_tools).map_err(|e| InquireError::Custom(e.into()))?;
+    let tools = mcp_servers
+        .list_tools(&cache, &env, Duration::from_secs(params.mcp_timeout))
+        .await;
+    let count: usize = tools.values().map(Vec::len).sum();
+    println!("✅ {}", format!("Found {count} tools.").green());
     let pb = ProgressBar::new_spinner();
     pb.enable_steady_tick(Duration::from_millis(300));
-    let description = mcp_servers.generate_description(pb).await;
-    println!("✅ {}", "Agent description generated".green());
+    let description = match params.description.or(answers.description.clone()) {
+        Some(description) => {
+            pb.finish_and_clear();
+            description
+        }
+        None => {
+            let generated = mcp_servers.generate_description(&tools, &llm, pb).await;
+            println!("✅ {}", "Agent description generated".green());
+            answers.prompt_or(generated, |generated| {
+                inquire::Text::new("Agent description")
+                    .with_initial_value(&generated)
+                    .with_help_message(
+                        "(edit the generated description, or press enter to keep it)",
+                    )
+                    .with_validator(ValueRequiredValidator::default())
+                    .prompt()
+            })?
+        }
+    };
+
+    let options = infer_options(&mcp_servers, &defaults, &tools, params.offline).await;
 
     // todo: alan what was the purpose of this...
     let runtimes: HashSet<Runtime> = mcp_servers
@@ -230,57 +592,44 @@ async fn mcp_wizard(params: McpParams) -> InquireResult<()> {
     let runtimes = Arc::new(runtimes);
     let mcps = Arc::new(mcp_servers);
 
-    let templater: Arc<dyn Template> = {
-        let runtimes = runtimes.clone();
-        let mcps = mcps.clone();
-        match framework {
-            Framework::Langchain => Arc::new(Langchain { runtimes, mcps }),
-            Framework::CoralRs => Arc::new(CoralRs { runtimes, mcps }),
+    let template_manifest = TemplateManifest::load(&extracted_path)
+        .map_err(|e| InquireError::Custom(e.into()))?
+        .map(Arc::new);
+    let templater: Arc<dyn Template> = match &template_manifest {
+        Some(manifest) if manifest.is_declarative() => {
+            let context =
+                TemplateContext::new(&agent_name, &description, &mcps, &options, &runtimes);
+            Arc::new(
+                Declarative::new(manifest.clone(), source, context, true)
+                    .map_err(|e| InquireError::Custom(e.into()))?,
+            )
         }
+        _ => framework
+            .templater(runtimes.clone(), mcps.clone())
+            .ok_or_else(|| {
+                InquireError::Custom(
+                    format!(
+                        "the {} template has no {} saying how to render it - templates of \
+                         registered frameworks need one",
+                        framework.name(),
+                        TemplateManifest::FILE
+                    )
+                    .into(),
+                )
+            })?,
     };
-
-    let include_file = match framework {
-        Framework::CoralRs => CoralRs::include_file,
-        Framework::Langchain => Langchain::include_file,
-    };
-
-    let dirs = directories_next::ProjectDirs::from("com", "coral-protocol", "coralizer")
-        .expect("cache dir");
-
-    let extracted_path = dirs.cache_dir().join("templates").join(templater.name());
-    fs::create_dir_all(&extracted_path).unwrap();
-
-    let (url, artefact_name) = templater.artifact();
-    let artefact_path = dirs.cache_dir().join("artefacts").join(artefact_name);
-    fs::create_dir_all(artefact_path.parent().unwrap(/* Safety: see above */)).unwrap();
-
-    let download = async || {
-        let pb = ProgressBar::new(10).with_message("Fetching template...");
-        let response = reqwest::get(url).await.unwrap().error_for_status().unwrap();
-        let mut bytes = pb.wrap_stream(response.bytes_stream());
-
-        let mut artefact = File::create(&artefact_path).unwrap();
-        while let Some(item) = bytes.next().await {
-            let chunk = item.unwrap();
-            artefact.write_all(&chunk).unwrap();
+    let include_file = {
+        let templater = templater.clone();
+        let manifest = template_manifest.clone();
+        move |entry: &ignore::DirEntry| match &manifest {
+            Some(manifest) => {
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                !manifest.excludes(entry.path(), is_dir)
+            }
+            None => templater.include_file(entry),
         }
-        artefact.flush().unwrap();
     };
 
-    // download if file doesn't exist, isn't a file, or is empty
-    if fs::metadata(&artefact_path)
-        .map(|meta| !meta.is_file() || meta.len() == 0)
-        .unwrap_or(true)
-    {
-        download().await;
-    }
-
-    let artefact = File::open(&artefact_path).unwrap();
-    let mut archive = zip::ZipArchive::new(artefact).unwrap();
-    archive
-        .extract_unwrapped_root_dir(&extracted_path, root_dir_common_filter)
-        .unwrap();
-
     let (tx, rx) = crossbeam::channel::unbounded();
 
     let agent_toml: Arc<Mutex<Option<PathBuf>>> = Arc::new(Mutex::new(None));
@@ -358,10 +707,12 @@ async fn mcp_wizard(params: McpParams) -> InquireResult<()> {
                 has_docker = true;
             }
 
-            match templater.is_templated_file(rel_path) {
+            match templater.templates_file(template_manifest.as_deref(), rel_path) {
                 true => {
                     let contents = std::fs::read_to_string(&path)?;
-                    let contents = templater.template(&contents);
+                    let contents = templater
+                        .inject(template_manifest.as_deref(), rel_path, &contents)
+                        .map_err(io::Error::other)?;
 
                     std::fs::write(final_path, contents)?;
                 }
@@ -373,49 +724,51 @@ async fn mcp_wizard(params: McpParams) -> InquireResult<()> {
         }
         println!("✅ {}", format!("Processed {file_count} files.").green());
 
-        edit_file_str(params.path.join("coral-agent.toml"), move |contents| {
-            let mut agent_toml: DocumentMut = contents.parse().expect("valid coral-agent.toml");
-
-            // NOTE: implicit assumption that all of our templates will have an agent table already
-            // defined
-            agent_toml["agent"]["name"] = value(agent_name.clone());
-            agent_toml["agent"]["description"] = value(description);
+        AgentManifest::edit(&params.path, |manifest| {
+            manifest.set_name(&agent_name).map_err(io::Error::other)?;
+            manifest
+                .set_description(&description)
+                .map_err(io::Error::other)?;
+            for (name, option) in &options {
+                manifest
+                    .set_option(name, &option.to_option())
+                    .map_err(io::Error::other)?;
+            }
 
-            let Some(options) = agent_toml.get_mut("options") else {
-                return Err(io::Error::other(
-                    "No options table found in coral-agent.toml!",
-                ));
-            };
-            let options = options.as_table_mut().expect("'options' key to be a table");
-            for opt in mcps
-                .servers
-                .values()
-                .filter_map(|mcp| mcp.options())
-                .flatten()
-            {
-                options[&opt]["type"] = "string".into();
-                options[&opt]["required"] = true.into();
-                if let Some(t) = options[&opt].as_inline_table_mut() {
-                    t.fmt()
+            match &template_manifest {
+                Some(template_manifest) => {
+                    template_manifest
+                        .post_process(&params.path, &agent_name, &description, |command| {
+                            answers.confirm_run(command)
+                        })
+                        .map_err(io::Error::other)?;
+                    templater.post_inject(&params.path)?;
                 }
+                None => templater.post_process(&params.path, &agent_name)?,
             }
 
-            templater.post_process(&params.path, &agent_name)?;
-
             if has_docker {
-                let image_name = inquire::Text::new("Name of Docker image")
-                    .with_help_message(
-                        "(the image name that would be used in a `docker run <IMAGE>`)",
-                    )
-                    .with_validator(ValueRequiredValidator::default())
-                    .prompt()
-                    .unwrap();
-                agent_toml["runtimes"]["docker"] = table();
-                agent_toml["runtimes"]["docker"]["image"] = value(image_name);
+                let image = answers
+                    .answer_or_prompt(answers.docker_image.clone(), "docker_image", || {
+                        inquire::Text::new("Name of Docker image")
+                            .with_help_message(
+                                "(the image name that would be used in a `docker run <IMAGE>`)",
+                            )
+                            .with_validator(ValueRequiredValidator::default())
+                            .prompt()
+                    })
+                    .map_err(io::Error::other)?;
+                manifest
+                    .set_runtime(&AgentRuntime::Docker(DockerRuntime {
+                        image,
+                        transport: None,
+                    }))
+                    .map_err(io::Error::other)?;
             }
 
-            Ok::<_, io::Error>(agent_toml.to_string())
+            Ok::<_, io::Error>(())
         })?;
+        agent_config::CoralAgent::load(&params.path).map_err(io::Error::other)?;
 
         // println!(
         //     "🔧 {} fixup -> {}...",
@@ -431,23 +784,227 @@ async fn mcp_wizard(params: McpParams) -> InquireResult<()> {
 
     // NOTE: don't print to stdout/err before thread closes to prevent garbage
     if let Err(e) = handle.join().expect("couldn't join on templating thread") {
-        eprintln!("Templating failed - {e:?}");
-        return Ok(());
+        return Err(InquireError::Custom(
+            format!("Templating failed - {e}").into(),
+        ));
     }
 
     Ok(())
 }
 
-fn link_command(params: LinkParams) -> anyhow::Result<()> {
-    let abs_path = fs::canonicalize(&params.path)?;
-    let toml_path = abs_path.join("coral-agent.toml");
-    if !toml_path.exists() {
-        anyhow::bail!("coral-agent.toml not found in {}", abs_path.display());
+async fn add_mcp_command(params: AddMcpParams) -> InquireResult<()> {
+    let root = params.path;
+    let agent = agent_config::CoralAgent::load(&root)
+        .map_err(|e| InquireError::Custom(e.into_boxed_dyn_error()))?;
+    let manifest = TemplateManifest::load(&root)
+        .map_err(|e| InquireError::Custom(e.into()))?
+        .map(Arc::new);
+    // agents of declarative templates are handled by their manifest alone
+    let declarative = manifest
+        .clone()
+        .filter(|manifest| manifest.is_declarative());
+    let framework = Framework::detect(&root);
+    if framework.is_none() && declarative.is_none() {
+        return Err(InquireError::Custom(
+            format!(
+                "could not detect the framework of the agent at '{}'",
+                root.display()
+            )
+            .into(),
+        ));
     }
 
-    let content = fs::read_to_string(&toml_path)?;
-    let config = agent_config::CoralAgent::from_toml(&content)
-        .map_err(|e| anyhow::anyhow!("Failed to parse coral-agent.toml: {}", e))?;
+    let name = match params.name {
+        Some(name) => name,
+        None => inquire::Text::new("Name of the MCP server")
+            .with_validator(ValueRequiredValidator::default())
+            .prompt()?,
+    };
+    let kind = match params.kind {
+        Some(kind) => kind,
+        None => inquire::Select::new(
+            "What kind of MCP server?",
+            McpKind::iter_variants().collect(),
+        )
+        .prompt()?,
+    };
+    let server = kind.wizard()?;
+
+    let runtimes: HashSet<Runtime> = server.runtime().into_iter().collect();
+    let mcps = Arc::new(McpServers {
+        servers: HashMap::from([(name.clone(), server)]),
+    });
+    let options = infer_options(&mcps, &HashMap::new(), &HashMap::new(), false).await;
+    let templater: Arc<dyn Template> = match (declarative.clone(), &framework) {
+        (Some(manifest), _) => {
+            let description = agent.agent.description.unwrap_or_default();
+            let context =
+                TemplateContext::new(&agent.agent.name, &description, &mcps, &options, &runtimes);
+            let source = TemplateSource::Dir { path: root.clone() };
+            Arc::new(
+                Declarative::new(manifest, source, context, false)
+                    .map_err(|e| InquireError::Custom(e.into()))?,
+            )
+        }
+        (None, Some(framework)) => framework
+            .templater(Arc::new(runtimes), mcps.clone())
+            .expect("detected frameworks are built in"),
+        (None, None) => unreachable!("checked above"),
+    };
+    // servers the framework can't write are refused before any file is touched
+    templater
+        .render_servers("")
+        .map_err(|e| InquireError::Custom(e.into()))?;
+
+    let mut templated = vec![];
+    for entry in WalkBuilder::new(&root).require_git(false).build() {
+        let entry = entry.map_err(io::Error::other)?;
+        let path = entry.path();
+        let rel_path = path.strip_prefix(&root).expect("path to be in base");
+        if path.is_file() && templater.templates_file(manifest.as_deref(), rel_path) {
+            templated.push(path.to_path_buf());
+        }
+    }
+    // checked before any file is touched, so a duplicate leaves the agent as it was
+    for path in &templated {
+        let contents = fs::read_to_string(path)?;
+        if templater.has_server(&contents, &name) {
+            let hint = match &declarative {
+                Some(_) => "pick another name",
+                None => "remove it first with `coralizer remove-mcp`",
+            };
+            return Err(InquireError::Custom(
+                format!("the agent already has an MCP server named '{name}' - {hint}").into(),
+            ));
+        }
+    }
+    for path in &templated {
+        let rel_path = path.strip_prefix(&root).expect("path to be in base");
+        println!(
+            "🔧 {:>18} fixup",
+            style(format!("'{}'", rel_path.display())).blue()
+        );
+        edit_file_str(path, |contents| {
+            templater
+                .inject(manifest.as_deref(), rel_path, &contents)
+                .map_err(io::Error::other)
+        })?;
+    }
+    if templated.is_empty() {
+        return Err(InquireError::Custom(
+            format!(
+                "no {} source file to add '{name}' to",
+                framework
+                    .as_ref()
+                    .map_or("agent", |framework| framework.name())
+            )
+            .into(),
+        ));
+    }
+
+    AgentManifest::edit(&root, |manifest| {
+        for (name, option) in &options {
+            manifest
+                .set_option(name, &option.to_option())
+                .map_err(io::Error::other)?;
+        }
+        Ok::<_, io::Error>(())
+    })?;
+
+    templater.post_inject(&root)?;
+
+    println!("✅ Added MCP server {}", format!("'{name}'").green());
+    Ok(())
+}
+
+fn remove_mcp_command(params: RemoveMcpParams) -> anyhow::Result<()> {
+    let root = params.path;
+    let name = params.name;
+    agent_config::CoralAgent::load(&root)?;
+    let manifest = TemplateManifest::load(&root)?;
+    if manifest
+        .as_ref()
+        .is_some_and(TemplateManifest::is_declarative)
+    {
+        anyhow::bail!(
+            "the agent at '{}' is rendered by its {} - its servers can't be told apart to remove \
+             one, so remove '{name}' from its source by hand",
+            root.display(),
+            TemplateManifest::FILE
+        );
+    }
+    let framework = Framework::detect(&root).ok_or_else(|| {
+        anyhow::anyhow!(
+            "could not detect the framework of the agent at '{}'",
+            root.display()
+        )
+    })?;
+    let templater = framework
+        .templater(
+            Default::default(),
+            Arc::new(McpServers {
+                servers: HashMap::new(),
+            }),
+        )
+        .expect("detected frameworks are built in");
+
+    let mut removed = vec![];
+    let mut remaining = vec![];
+    for entry in WalkBuilder::new(&root).require_git(false).build() {
+        let entry = entry?;
+        let path = entry.path();
+        let rel_path = path.strip_prefix(&root).expect("path to be in base");
+        if !path.is_file() || !templater.templates_file(manifest.as_ref(), rel_path) {
+            continue;
+        }
+        let contents = fs::read_to_string(path)?;
+        match templater.remove_server(&contents, &name) {
+            Some((contents, server)) => {
+                println!(
+                    "🔧 {:>18} fixup",
+                    style(format!("'{}'", rel_path.display())).blue()
+                );
+                fs::write(path, &contents)?;
+                removed.push(server);
+                remaining.push(contents);
+            }
+            None => remaining.push(contents),
+        }
+    }
+    if removed.is_empty() {
+        anyhow::bail!("no MCP server named '{name}' found in {}", root.display());
+    }
+
+    // only drop options the removed server used that nothing else refers to anymore
+    let options = |sources: &[String]| -> HashSet<String> {
+        sources
+            .iter()
+            .flat_map(|src| templater.server_options(src))
+            .collect()
+    };
+    let (removed, remaining) = (options(&removed), options(&remaining));
+    AgentManifest::edit(&root, |manifest| {
+        let unused = manifest
+            .option_names()
+            .into_iter()
+            .filter(|opt| removed.contains(opt) && !remaining.contains(opt))
+            .collect_vec();
+        for opt in unused {
+            println!("🗑️ Removing option {}", opt.yellow());
+            manifest.remove_option(&opt);
+        }
+        Ok::<_, io::Error>(())
+    })?;
+
+    templater.post_inject(&root)?;
+
+    println!("✅ Removed MCP server {}", format!("'{name}'").green());
+    Ok(())
+}
+
+fn link_command(params: LinkParams) -> anyhow::Result<()> {
+    let abs_path = fs::canonicalize(&params.path)?;
+    let config = agent_config::CoralAgent::load(&abs_path)?;
 
     let name = &config.agent.name;
     let version = &config.agent.version;
@@ -487,14 +1044,7 @@ fn link_command(params: LinkParams) -> anyhow::Result<()> {
 
 fn unlink_command(params: LinkParams) -> anyhow::Result<()> {
     let abs_path = fs::canonicalize(&params.path)?;
-    let toml_path = abs_path.join("coral-agent.toml");
-    if !toml_path.exists() {
-        anyhow::bail!("coral-agent.toml not found in {}", abs_path.display());
-    }
-
-    let content = fs::read_to_string(&toml_path)?;
-    let config = agent_config::CoralAgent::from_toml(&content)
-        .map_err(|e| anyhow::anyhow!("Failed to parse coral-agent.toml: {}", e))?;
+    let config = agent_config::CoralAgent::load(&abs_path)?;
 
     let name = &config.agent.name;
     let version = &config.agent.version;
@@ -544,14 +1094,7 @@ fn unlink_command(params: LinkParams) -> anyhow::Result<()> {
 
 fn updeletelink_command(params: LinkParams) -> anyhow::Result<()> {
     let abs_path = fs::canonicalize(&params.path)?;
-    let toml_path = abs_path.join("coral-agent.toml");
-    if !toml_path.exists() {
-        anyhow::bail!("coral-agent.toml not found in {}", abs_path.display());
-    }
-
-    let content = fs::read_to_string(&toml_path)?;
-    let config = agent_config::CoralAgent::from_toml(&content)
-        .map_err(|e| anyhow::anyhow!("Failed to parse coral-agent.toml: {}", e))?;
+    let config = agent_config::CoralAgent::load(&abs_path)?;
 
     let name = &config.agent.name;
 
@@ -570,10 +1113,10 @@ fn updeletelink_command(params: LinkParams) -> anyhow::Result<()> {
     for entry in fs::read_dir(&agents_dir)? {
         let entry = entry?;
         let path = entry.path();
-        if let Some(version_str) = path.file_name().and_then(|s| s.to_str()) {
-            if let Ok(version) = Version::parse(version_str) {
-                versions.push((version, path));
-            }
+        if let Some(version_str) = path.file_name().and_then(|s| s.to_str())
+            && let Ok(version) = Version::parse(version_str)
+        {
+            versions.push((version, path));
         }
     }
 
@@ -628,20 +1171,314 @@ fn updeletelink_command(params: LinkParams) -> anyhow::Result<()> {
     Ok(())
 }
 
+async fn tools_command(params: ToolsParams) -> anyhow::Result<()> {
+    let mut mcp_servers = McpServers::read(&params.mcp_servers_path)?;
+    let env = params.mcp_env.load(&mcp_servers)?;
+    // same config as `mcp` lists the tools with, so both share cached listings
+    mcp_servers.bind_options();
+
+    let tools = mcp_servers
+        .list_tools(
+            &ToolCache::new(params.refresh_tools)?,
+            &env,
+            Duration::from_secs(params.mcp_timeout),
+        )
+        .await;
+    let tools: BTreeMap<_, _> = tools.into_iter().collect();
+
+    match params.format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&tools)?),
+        Format::Text => {
+            for (server, tools) in tools {
+                println!("{} ({} tools)", server.blue(), tools.len());
+                for tool in tools.iter().sorted_by(|a, b| a.name.cmp(&b.name)) {
+                    let description = tool.description.as_deref().unwrap_or_default();
+                    match description.lines().next() {
+                        Some(line) => println!("  {} - {line}", tool.name.green()),
+                        None => println!("  {}", tool.name.green()),
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether every server could be inspected.
+async fn inspect_command(params: InspectParams) -> anyhow::Result<bool> {
+    let mcp_servers = McpServers::read(&params.mcp_servers_path)?;
+    let env = params.mcp_env.load(&mcp_servers)?;
+
+    let pb = ProgressBar::new_spinner();
+    pb.enable_steady_tick(Duration::from_millis(300));
+    let mut inspections = BTreeMap::new();
+    for (name, server) in mcp_servers.servers.iter().sorted_by_key(|(name, _)| *name) {
+        pb.set_message(format!("Inspecting '{name}'"));
+        inspections.insert(name.clone(), inspect::inspect(server, &env).await);
+    }
+    pb.finish_and_clear();
+
+    match params.json {
+        true => println!("{}", serde_json::to_string_pretty(&inspections)?),
+        false => {
+            for (name, inspection) in &inspections {
+                inspection.print(name);
+            }
+        }
+    }
+    Ok(inspections.values().all(|i| i.errors.is_empty()))
+}
+
+async fn call_command(params: CallParams) -> InquireResult<()> {
+    let mcp_servers =
+        McpServers::read(&params.mcp_servers_path).map_err(|e| InquireError::Custom(e.into()))?;
+    let Some(server) = mcp_servers.servers.get(&params.server) else {
+        return Err(InquireError::Custom(
+            format!(
+                "no server named '{}' - the config has {}",
+                params.server,
+                mcp_servers.servers.keys().sorted().join(", ")
+            )
+            .into(),
+        ));
+    };
+
+    let env = params
+        .mcp_env
+        .load(&mcp_servers)
+        .map_err(|e| InquireError::Custom(e.into()))?;
+
+    let pb = ProgressBar::new_spinner();
+    pb.enable_steady_tick(Duration::from_millis(300));
+    pb.set_message(format!("Starting '{}'", params.server));
+    let client = mcp_client::make_client(server, &env).await;
+    pb.finish_and_clear();
+    let client = client.map_err(|e| {
+        let error = format!("failed to start '{}' - {e}", params.server);
+        InquireError::Custom(env.redact(&error).into())
+    })?;
+
+    let result = call::repl(&client, &env).await;
+    let _ = client.cancel().await;
+    result
+}
+
+/// The URL of the remote server `name` in the MCP config at `path`
+fn remote_server_url(path: &std::path::Path, name: &str) -> anyhow::Result<String> {
+    let mcp_servers = McpServers::read(path)?;
+    match mcp_servers.servers.get(name) {
+        Some(McpServer::Http { url, .. } | McpServer::Sse { url, .. }) => Ok(url.clone()),
+        Some(McpServer::Stdio { .. }) => {
+            anyhow::bail!("'{name}' is a stdio server - only remote servers need authorizing")
+        }
+        None => anyhow::bail!(
+            "no server named '{name}' - the config has {}",
+            mcp_servers.servers.keys().sorted().join(", ")
+        ),
+    }
+}
+
+async fn login_command(params: LoginParams) -> anyhow::Result<()> {
+    let url = remote_server_url(&params.mcp_servers_path, &params.server)?;
+    let credentials = oauth::authorize(&url, params.client_id, |authorize_url| {
+        println!("🔑 Authorize coralizer with '{}' at:", params.server);
+        println!("   {}", authorize_url.as_str().blue());
+        if !params.no_browser {
+            open_browser(authorize_url.as_str());
+        }
+    })
+    .await?;
+    CredentialStore::in_config_dir()?.put(&credentials)?;
+    println!(
+        "✅ {}",
+        format!("Authorized with '{}'", params.server).green()
+    );
+    Ok(())
+}
+
+fn logout_command(params: LogoutParams) -> anyhow::Result<()> {
+    let url = remote_server_url(&params.mcp_servers_path, &params.server)?;
+    match CredentialStore::in_config_dir()?.remove(&url)? {
+        true => println!("🗑️ Forgot the tokens of '{}'", params.server),
+        false => println!("'{}' wasn't authorized", params.server),
+    }
+    Ok(())
+}
+
+async fn cache_command(params: CacheParams) -> anyhow::Result<()> {
+    let cache = TemplateCache::in_cache_dir()?;
+    match params.command {
+        CacheCommand::List {
+            format,
+            template_registry,
+        } => {
+            let registry = TemplateRegistry::load(template_registry.as_deref(), false).await?;
+            let sources = template_sources(&registry)?;
+            let entries = cache.entries(sources.into_iter().map(|(_, source)| source))?;
+            match format {
+                Format::Json => println!("{}", serde_json::to_string_pretty(&entries)?),
+                Format::Text => {
+                    for entry in &entries {
+                        let size = HumanBytes(entry.size).to_string();
+                        match entry.used {
+                            true => println!("{size:>11}  {}", entry.path.display()),
+                            false => {
+                                println!(
+                                    "{size:>11}  {} {}",
+                                    entry.path.display(),
+                                    "(unused)".yellow()
+                                )
+                            }
+                        }
+                    }
+                    let unused = entries.iter().filter(|entry| !entry.used);
+                    println!(
+                        "{} in {} entries, {} of it unused",
+                        HumanBytes(entries.iter().map(|entry| entry.size).sum()),
+                        entries.len(),
+                        HumanBytes(unused.map(|entry| entry.size).sum())
+                    );
+                }
+            }
+        }
+        CacheCommand::Prune { template_registry } => {
+            let registry = TemplateRegistry::load(template_registry.as_deref(), false).await?;
+            let sources = template_sources(&registry)?;
+            let removed = cache.prune(sources.into_iter().map(|(_, source)| source))?;
+            println!(
+                "🗑️ Removed {} unused entries ({})",
+                removed.len(),
+                HumanBytes(removed.iter().map(|entry| entry.size).sum())
+            );
+        }
+        CacheCommand::Clear => {
+            let freed = cache.clear()?;
+            println!("🗑️ Cleared the template cache ({})", HumanBytes(freed));
+        }
+    }
+    Ok(())
+}
+
+async fn templates_command(params: TemplatesParams) -> anyhow::Result<()> {
+    match params.command {
+        TemplatesCommand::Export {
+            dir,
+            template_registry,
+        } => {
+            let registry = TemplateRegistry::load(template_registry.as_deref(), false).await?;
+            let sources = template_sources(&registry)?;
+            let cache = TemplateCache::in_cache_dir()?;
+            let exported =
+                TemplateBundle::export(&sources, &registry.frameworks, &dir, &cache).await?;
+            println!(
+                "✅ Bundled the {} templates into '{}'",
+                exported.join(", "),
+                dir.display()
+            );
+        }
+        TemplatesCommand::Import { dir } => {
+            let imported = TemplateBundle::import(&dir, &TemplateCache::in_cache_dir()?)?;
+            println!("✅ Cached the {} templates", imported.join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// Where every framework's template is fetched from, as `mcp` would without `--template`
+fn template_sources(registry: &TemplateRegistry) -> anyhow::Result<Vec<(String, TemplateSource)>> {
+    Ok(FrameworkRegistry::new(registry)?
+        .iter()
+        .map(|framework| {
+            let source = registry.source(framework.id(), framework.default_source());
+            (framework.id().to_string(), source)
+        })
+        .collect())
+}
+
+/// Best effort - the URL is printed anyway
+fn open_browser(url: &str) {
+    let mut command = match std::env::consts::OS {
+        "macos" => std::process::Command::new("open"),
+        "windows" => {
+            let mut command = std::process::Command::new("cmd");
+            command.args(["/c", "start", ""]);
+            command
+        }
+        _ => std::process::Command::new("xdg-open"),
+    };
+    let _ = command
+        .arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+}
+
+/// Returns whether the agent passed, printing every diagnostic found.
+fn validate_command(params: ValidateParams) -> bool {
+    let diagnostics = validate::validate(&params.path);
+    let passed = !diagnostics.iter().any(|d| {
+        d.severity == validate::Severity::Error
+            || (params.deny_warnings && d.severity == validate::Severity::Warning)
+    });
+
+    match params.format {
+        Format::Json => println!(
+            "{}",
+            serde_json::json!({ "valid": passed, "diagnostics": diagnostics })
+        ),
+        Format::Text => {
+            for diagnostic in &diagnostics {
+                let line = diagnostic.to_string();
+                match diagnostic.severity {
+                    validate::Severity::Error => eprintln!("{}", line.red()),
+                    validate::Severity::Warning => eprintln!("{}", line.yellow()),
+                }
+            }
+            match passed {
+                true => println!("✅ {}", "Agent is valid".green()),
+                false => eprintln!("{} agent is invalid", "Error:".red()),
+            }
+        }
+    }
+    passed
+}
+
+fn exit_on_inquire_error(result: InquireResult<()>) {
+    if let Err(e) = result {
+        match e {
+            inquire::InquireError::OperationCanceled
+            | inquire::InquireError::OperationInterrupted => {
+                eprintln!("\nCancelled.");
+            }
+            e => {
+                eprintln!("{} {}", "Error:".red(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
-    let cli = Cli::parse();
+    // registered frameworks are listed by `--help` and completions too
+    let frameworks = TemplateRegistry::load_default()
+        .and_then(|registry| FrameworkRegistry::new(&registry))
+        .or_else(|_| FrameworkRegistry::new(&TemplateRegistry::default()))
+        .expect("built-in frameworks register");
+    let matches = Cli::command()
+        .mut_subcommand("mcp", |mcp| {
+            mcp.mut_arg("framework", |arg| arg.value_parser(frameworks.id_parser()))
+        })
+        .get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
     match cli {
-        Cli::Mcp(params) => {
-            if let Err(e) = mcp_wizard(params).await {
-                match e {
-                    inquire::InquireError::OperationCanceled
-                    | inquire::InquireError::OperationInterrupted => {
-                        eprintln!("\nCancelled.");
-                    }
-                    e => panic!("{e:?}"),
-                }
+        Cli::Mcp(params) => exit_on_inquire_error(mcp_wizard(*params).await),
+        Cli::AddMcp(params) => exit_on_inquire_error(add_mcp_command(params).await),
+        Cli::RemoveMcp(params) => {
+            if let Err(e) = remove_mcp_command(params) {
+                eprintln!("{} {}", "Error:".red(), e);
+                std::process::exit(1);
             }
         }
         Cli::Link(params) => {
@@ -659,5 +1496,49 @@ async fn main() {
                 eprintln!("{} {}", "Error:".red(), e);
             }
         }
+        Cli::Tools(params) => {
+            if let Err(e) = tools_command(params).await {
+                eprintln!("{} {}", "Error:".red(), e);
+                std::process::exit(1);
+            }
+        }
+        Cli::Inspect(params) => match inspect_command(params).await {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("{} {}", "Error:".red(), e);
+                std::process::exit(1);
+            }
+        },
+        Cli::Call(params) => exit_on_inquire_error(call_command(params).await),
+        Cli::Login(params) => {
+            if let Err(e) = login_command(params).await {
+                eprintln!("{} {}", "Error:".red(), e);
+                std::process::exit(1);
+            }
+        }
+        Cli::Logout(params) => {
+            if let Err(e) = logout_command(params) {
+                eprintln!("{} {}", "Error:".red(), e);
+                std::process::exit(1);
+            }
+        }
+        Cli::Cache(params) => {
+            if let Err(e) = cache_command(params).await {
+                eprintln!("{} {}", "Error:".red(), e);
+                std::process::exit(1);
+            }
+        }
+        Cli::Templates(params) => {
+            if let Err(e) = templates_command(params).await {
+                eprintln!("{} {}", "Error:".red(), e);
+                std::process::exit(1);
+            }
+        }
+        Cli::Validate(params) => {
+            if !validate_command(params) {
+                std::process::exit(1);
+            }
+        }
     }
 }
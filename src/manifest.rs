@@ -0,0 +1,340 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use semver::{BuildMetadata, Prerelease, Version};
+use toml_edit::{Array, DocumentMut, Item, Table, TableLike, TomlError, Value};
+
+use crate::agent_config::{AgentOption, DockerRuntime, ExecutableRuntime, FunctionRuntime};
+use crate::edit::edit_file_str;
+
+/// A `coral-agent.toml` being edited in place. Unlike [`crate::agent_config::CoralAgent`] this keeps
+/// comments, formatting and key order, and creates any missing tables as they're needed.
+pub struct AgentManifest {
+    doc: DocumentMut,
+}
+
+/// One of the `[runtimes]` of an agent
+#[derive(Debug, Clone)]
+pub enum AgentRuntime {
+    Docker(DockerRuntime),
+    Executable(ExecutableRuntime),
+    Function(FunctionRuntime),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum VersionBump {
+    Major,
+    Minor,
+    Patch,
+}
+
+impl AgentManifest {
+    pub fn parse(content: &str) -> Result<Self, TomlError> {
+        Ok(AgentManifest {
+            doc: content.parse()?,
+        })
+    }
+
+    /// Edits the `coral-agent.toml` in `root`, only writing it back if `edit_fn` succeeds.
+    pub fn edit<E, F>(root: &Path, edit_fn: F) -> Result<(), E>
+    where
+        E: Error + From<io::Error>,
+        F: FnOnce(&mut AgentManifest) -> Result<(), E>,
+    {
+        edit_file_str(root.join("coral-agent.toml"), |contents| {
+            let mut manifest = Self::parse(&contents).map_err(io::Error::other)?;
+            edit_fn(&mut manifest)?;
+            Ok::<_, E>(manifest.to_string())
+        })
+    }
+
+    /// The table at `keys`, creating any that are missing. New tables are implicit, so
+    /// `[runtimes.docker]` doesn't get an empty `[runtimes]` header above it. A value that isn't a
+    /// table is an error rather than replaced, so nothing of the agent's is lost.
+    fn table_item(&mut self, keys: &[&str]) -> anyhow::Result<&mut Item> {
+        let mut item = self.doc.as_item_mut();
+        for (i, key) in keys.iter().enumerate() {
+            let table = item.as_table_like_mut().expect("checked to be a table");
+            item = table.entry(key).or_insert_with(|| {
+                let mut table = Table::new();
+                table.set_implicit(true);
+                Item::Table(table)
+            });
+            if !item.is_table_like() {
+                anyhow::bail!(
+                    "expected '{}' in coral-agent.toml to be a table, found {}",
+                    keys[..=i].join("."),
+                    item.type_name()
+                );
+            }
+        }
+        Ok(item)
+    }
+
+    fn table(&mut self, keys: &[&str]) -> anyhow::Result<&mut dyn TableLike> {
+        let item = self.table_item(keys)?;
+        Ok(item.as_table_like_mut().expect("checked to be a table"))
+    }
+
+    pub fn set_name(&mut self, name: &str) -> anyhow::Result<()> {
+        set(self.table(&["agent"])?, "name", name);
+        Ok(())
+    }
+
+    pub fn set_description(&mut self, description: &str) -> anyhow::Result<()> {
+        set(self.table(&["agent"])?, "description", description);
+        Ok(())
+    }
+
+    /// Bumps `agent.version`, returning the new version
+    pub fn bump_version(&mut self, bump: VersionBump) -> anyhow::Result<Version> {
+        let agent = self.table(&["agent"])?;
+        let current = agent
+            .get("version")
+            .and_then(Item::as_str)
+            .ok_or_else(|| anyhow::anyhow!("no 'agent.version' to bump"))?;
+        let mut version = Version::parse(current)
+            .map_err(|e| anyhow::anyhow!("'{current}' is not a semver version - {e}"))?;
+        match bump {
+            VersionBump::Major => {
+                version.major += 1;
+                version.minor = 0;
+                version.patch = 0;
+            }
+            VersionBump::Minor => {
+                version.minor += 1;
+                version.patch = 0;
+            }
+            VersionBump::Patch => version.patch += 1,
+        }
+        version.pre = Prerelease::EMPTY;
+        version.build = BuildMetadata::EMPTY;
+        set(agent, "version", version.to_string());
+        Ok(version)
+    }
+
+    /// Names of every option in `[options]`
+    pub fn option_names(&self) -> Vec<String> {
+        self.doc
+            .get("options")
+            .and_then(Item::as_table_like)
+            .into_iter()
+            .flat_map(|options| options.iter().map(|(name, _)| name.to_string()))
+            .collect()
+    }
+
+    /// Adds or updates the option `name`, keeping any keys of it we don't know about
+    pub fn set_option(&mut self, name: &str, option: &AgentOption) -> anyhow::Result<()> {
+        let item = self.table_item(&["options", name])?;
+        let table = item.as_table_like_mut().expect("checked to be a table");
+        set(table, "type", option.kind.as_str());
+        set_or_remove(table, "description", option.description.as_deref());
+        set_or_remove(table, "required", option.required);
+        set_or_remove(table, "default", option.default.as_ref());
+        set_or_remove(table, "secret", option.secret.then_some(true));
+        if let Some(t) = item.as_inline_table_mut() {
+            t.fmt()
+        }
+        Ok(())
+    }
+
+    /// Removes the option `name`, returning whether there was one
+    pub fn remove_option(&mut self, name: &str) -> bool {
+        self.doc
+            .get_mut("options")
+            .and_then(Item::as_table_like_mut)
+            .and_then(|options| options.remove(name))
+            .is_some()
+    }
+
+    /// Adds or updates a runtime, keeping any keys of it we don't know about
+    pub fn set_runtime(&mut self, runtime: &AgentRuntime) -> anyhow::Result<()> {
+        let name = match runtime {
+            AgentRuntime::Docker(_) => "docker",
+            AgentRuntime::Executable(_) => "executable",
+            AgentRuntime::Function(_) => "function",
+        };
+        let table = self.table(&["runtimes", name])?;
+        let transport = match runtime {
+            AgentRuntime::Docker(docker) => {
+                set(table, "image", &docker.image);
+                docker.transport
+            }
+            AgentRuntime::Executable(executable) => {
+                set(table, "path", &executable.path);
+                set(
+                    table,
+                    "arguments",
+                    executable.arguments.iter().collect::<Array>(),
+                );
+                executable.transport
+            }
+            AgentRuntime::Function(_) => None,
+        };
+        set_or_remove(table, "transport", transport.map(|t| t.as_str()));
+        Ok(())
+    }
+}
+
+/// Sets `table[key]`, keeping the comments & whitespace around the value it replaces
+fn set(table: &mut dyn TableLike, key: &str, new: impl Into<Value>) {
+    let mut new = new.into();
+    if let Some(old) = table.get(key).and_then(Item::as_value) {
+        *new.decor_mut() = old.decor().clone();
+    }
+    table.insert(key, Item::Value(new));
+}
+
+fn set_or_remove(table: &mut dyn TableLike, key: &str, new: Option<impl Into<Value>>) {
+    match new {
+        Some(new) => set(table, key, new),
+        None => {
+            table.remove(key);
+        }
+    }
+}
+
+impl fmt::Display for AgentManifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent_config::{OptionType, OptionValue, Transport};
+
+    const MANIFEST: &str = r#"# the agent
+edition = 2
+
+[agent]
+name = "agent" # what it's called
+version = "1.2.3-beta+build"
+
+[options.API_KEY]
+type = "string"
+required = true
+owner = "ops" # not ours to touch
+"#;
+
+    fn option(default: Option<&str>) -> AgentOption {
+        AgentOption {
+            kind: OptionType::String,
+            required: default.is_none().then_some(true),
+            description: Some("The key".to_string()),
+            default: default.map(|d| OptionValue::String(d.to_string())),
+            secret: false,
+        }
+    }
+
+    #[test]
+    fn round_trips_untouched() {
+        let manifest = AgentManifest::parse(MANIFEST).unwrap();
+        assert_eq!(manifest.to_string(), MANIFEST);
+    }
+
+    #[test]
+    fn keeps_comments_and_order() {
+        let mut manifest = AgentManifest::parse(MANIFEST).unwrap();
+        manifest.set_name("renamed").unwrap();
+        manifest.set_description("Does things").unwrap();
+        assert_eq!(
+            manifest.to_string(),
+            MANIFEST
+                .replace("\"agent\" # what", "\"renamed\" # what")
+                .replace("build\"\n", "build\"\ndescription = \"Does things\"\n")
+        );
+    }
+
+    #[test]
+    fn creates_missing_tables() {
+        let mut manifest = AgentManifest::parse("edition = 2\n").unwrap();
+        manifest.set_name("agent").unwrap();
+        manifest
+            .set_option("LOG_LEVEL", &option(Some("info")))
+            .unwrap();
+        manifest
+            .set_runtime(&AgentRuntime::Docker(DockerRuntime {
+                image: "org/agent".to_string(),
+                transport: Some(Transport::Sse),
+            }))
+            .unwrap();
+        assert_eq!(
+            manifest.to_string(),
+            r#"edition = 2
+
+[agent]
+name = "agent"
+
+[options.LOG_LEVEL]
+type = "string"
+description = "The key"
+default = "info"
+
+[runtimes.docker]
+image = "org/agent"
+transport = "sse"
+"#
+        );
+    }
+
+    #[test]
+    fn updates_options_keeping_unknown_keys() {
+        let mut manifest = AgentManifest::parse(MANIFEST).unwrap();
+        manifest.set_option("API_KEY", &option(Some("k"))).unwrap();
+        let edited = manifest.to_string();
+        assert!(edited.contains("owner = \"ops\" # not ours to touch"));
+        assert!(edited.contains("default = \"k\""));
+        assert!(!edited.contains("required"));
+
+        assert_eq!(manifest.option_names(), ["API_KEY"]);
+        assert!(manifest.remove_option("API_KEY"));
+        assert!(!manifest.remove_option("API_KEY"));
+        assert!(manifest.option_names().is_empty());
+    }
+
+    #[test]
+    fn refuses_to_replace_values_with_tables() {
+        for (toml, key) in [
+            ("options = \"x\"\n", "options"),
+            ("[options]\nLOG_LEVEL = 1\n", "options.LOG_LEVEL"),
+        ] {
+            let mut manifest = AgentManifest::parse(toml).unwrap();
+            let e = manifest.set_option("LOG_LEVEL", &option(None)).unwrap_err();
+            assert!(e.to_string().contains(&format!("'{key}'")), "{e}");
+            assert_eq!(manifest.to_string(), toml);
+        }
+
+        let mut manifest = AgentManifest::parse("agent = [1]\n").unwrap();
+        let e = manifest.set_name("agent").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "expected 'agent' in coral-agent.toml to be a table, found array"
+        );
+    }
+
+    #[test]
+    fn bumps_versions() {
+        for (bump, expected) in [
+            (VersionBump::Major, "2.0.0"),
+            (VersionBump::Minor, "1.3.0"),
+            (VersionBump::Patch, "1.2.4"),
+        ] {
+            let mut manifest = AgentManifest::parse(MANIFEST).unwrap();
+            assert_eq!(manifest.bump_version(bump).unwrap().to_string(), expected);
+            assert!(
+                manifest
+                    .to_string()
+                    .contains(&format!("version = \"{expected}\"\n"))
+            );
+        }
+
+        let mut invalid = AgentManifest::parse("[agent]\nversion = \"1.0\"\n").unwrap();
+        assert!(invalid.bump_version(VersionBump::Patch).is_err());
+        let mut missing = AgentManifest::parse("edition = 2\n").unwrap();
+        assert!(missing.bump_version(VersionBump::Patch).is_err());
+    }
+}
@@ -1,15 +1,19 @@
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue};
 use rmcp::{
     RmcpError, ServiceExt as _,
     model::{ClientCapabilities, ClientInfo, Implementation, InitializeRequestParam},
     service::RunningService,
     transport::{
         ConfigureCommandExt as _, SseClientTransport, StreamableHttpClientTransport,
-        TokioChildProcess,
+        TokioChildProcess, sse_client::SseClientConfig,
+        streamable_http_client::StreamableHttpClientTransportConfig,
     },
 };
 use tokio::process::Command;
 
-use crate::mcp_server::McpServer;
+use crate::mcp_env::McpEnv;
+use crate::mcp_server::{McpServer, looks_secret};
+use crate::oauth::{self, CredentialStore};
 
 pub enum Client {
     Local(RunningService<rmcp::RoleClient, ()>),
@@ -35,7 +39,8 @@ impl Client {
     }
 }
 
-pub async fn make_client(mcp: &McpServer) -> anyhow::Result<Client> {
+/// Starts or connects to `mcp`, giving it the env vars or headers `env` has values for
+pub async fn make_client(mcp: &McpServer, env: &McpEnv) -> anyhow::Result<Client> {
     let client_info = || ClientInfo {
         protocol_version: Default::default(),
         capabilities: ClientCapabilities::default(),
@@ -48,7 +53,9 @@ pub async fn make_client(mcp: &McpServer) -> anyhow::Result<Client> {
         },
     };
     Ok(match mcp {
-        McpServer::Stdio { command, args, env } => Client::Local(
+        McpServer::Stdio {
+            command, args, cwd, ..
+        } => Client::Local(
             /*
                😡
 
@@ -67,9 +74,10 @@ pub async fn make_client(mcp: &McpServer) -> anyhow::Result<Client> {
                     })
                     .configure(|cmd| {
                         cmd.args(args);
-                        if let Some(env) = env {
-                            cmd.envs(env.keys().map(|k| (k.to_string(), "dummy".to_string())));
+                        if let Some(cwd) = cwd {
+                            cmd.current_dir(cwd);
                         }
+                        cmd.envs(env.server_env(mcp));
                     }),
                 )
                 .map_err(RmcpError::transport_creation::<TokioChildProcess>)?,
@@ -77,16 +85,60 @@ pub async fn make_client(mcp: &McpServer) -> anyhow::Result<Client> {
             .await?,
         ),
         McpServer::Sse { url, .. } => {
-            let client = client_info()
-                .serve(SseClientTransport::start(url.to_owned()).await?)
-                .await?;
-            Client::Network(client)
+            let config = SseClientConfig {
+                sse_endpoint: url.as_str().into(),
+                ..Default::default()
+            };
+            let client = http_client(url, mcp, env).await?;
+            let transport = SseClientTransport::start_with_client(client, config)
+                .await
+                .map_err(|e| unauthorized_hint(e.into()))?;
+            Client::Network(client_info().serve(transport).await?)
         }
         McpServer::Http { url, .. } => {
+            let transport = StreamableHttpClientTransport::with_client(
+                http_client(url, mcp, env).await?,
+                StreamableHttpClientTransportConfig::with_uri(url.as_str()),
+            );
             let client = client_info()
-                .serve(StreamableHttpClientTransport::from_uri(url.to_owned()))
-                .await?;
+                .serve(transport)
+                .await
+                .map_err(|e| unauthorized_hint(e.into()))?;
             Client::Network(client)
         }
     })
 }
+
+/// An HTTP client sending the headers of a remote server with every request - and, unless they
+/// include one, an `Authorization` header with the token from `coralizer login`
+async fn http_client(url: &str, mcp: &McpServer, env: &McpEnv) -> anyhow::Result<reqwest::Client> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in env.server_headers(mcp) {
+        let header_name = HeaderName::try_from(name.as_str())
+            .map_err(|e| anyhow::anyhow!("invalid header name '{name}' - {e}"))?;
+        let mut value = HeaderValue::try_from(value)
+            .map_err(|e| anyhow::anyhow!("invalid value for header '{name}' - {e}"))?;
+        value.set_sensitive(looks_secret(&name));
+        headers.insert(header_name, value);
+    }
+    if !headers.contains_key(AUTHORIZATION)
+        && let Ok(store) = CredentialStore::in_config_dir()
+        && let Some(token) = oauth::access_token(&store, url).await
+    {
+        let mut value = HeaderValue::try_from(format!("Bearer {token}"))?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    Ok(reqwest::Client::builder()
+        .default_headers(headers)
+        .build()?)
+}
+
+/// Points out `coralizer login` when a remote server turns us away for not being authorized
+fn unauthorized_hint(e: anyhow::Error) -> anyhow::Error {
+    let message = e.to_string();
+    match message.contains("401") || message.contains("Auth required") {
+        true => e.context("the server requires authorization - run `coralizer login` first"),
+        false => e,
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::mcp_server::{McpServer, McpServers, is_placeholder, looks_secret};
+
+/// Shown instead of a secret's value
+const REDACTED: &str = "***";
+
+/// The real values MCP servers' env vars and headers are given while listing their tools, looked
+/// up by name in order from:
+///
+/// 1. `--env KEY=VALUE` flags
+/// 2. values written in the MCP config itself (unless they're placeholders like `<YOUR_KEY>`)
+/// 3. the process environment
+/// 4. the `--env-file` (`.env` by default)
+#[derive(Debug, Clone, Default)]
+pub struct McpEnv {
+    flags: HashMap<String, String>,
+    config: HashMap<String, String>,
+    env_file: HashMap<String, String>,
+}
+
+impl McpEnv {
+    /// Must be made before [`McpServers::bind_options`] replaces the config's values.
+    pub fn new(
+        servers: &McpServers,
+        flags: &[(String, String)],
+        env_file: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        let env_file = match env_file {
+            Some(path) => read_env_file(path)
+                .map_err(|e| anyhow::anyhow!("couldn't read '{}' - {e}", path.display()))?,
+            None => read_env_file(Path::new(".env")).unwrap_or_default(),
+        };
+        let config = servers
+            .servers
+            .values()
+            .flat_map(|server| match server {
+                McpServer::Stdio { env, .. } => env,
+                McpServer::Sse { headers, .. } | McpServer::Http { headers, .. } => headers,
+            })
+            .flatten()
+            .filter(|(k, v)| !is_placeholder(v) && v != k)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        Ok(McpEnv {
+            flags: flags.iter().cloned().collect(),
+            config,
+            env_file,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.flags
+            .get(name)
+            .or_else(|| self.config.get(name))
+            .cloned()
+            .or_else(|| env::var(name).ok())
+            .or_else(|| self.env_file.get(name).cloned())
+    }
+
+    /// The env vars a stdio server is started with. Vars with no value anywhere - not even in
+    /// the server's own `envFile` - are set to `"dummy"`, which is enough for many servers to start.
+    pub fn server_env(&self, server: &McpServer) -> HashMap<String, String> {
+        let McpServer::Stdio {
+            env: Some(env),
+            env_file,
+            cwd,
+            ..
+        } = server
+        else {
+            return HashMap::new();
+        };
+        let server_env_file = env_file
+            .as_ref()
+            .map(|path| match cwd {
+                Some(cwd) => Path::new(cwd).join(path),
+                None => PathBuf::from(path),
+            })
+            .and_then(|path| read_env_file(&path).ok())
+            .unwrap_or_default();
+        env.keys()
+            .map(|name| {
+                let value = self
+                    .get(name)
+                    .or_else(|| server_env_file.get(name).cloned())
+                    .unwrap_or_else(|| "dummy".to_string());
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    /// The headers a remote server is sent - those with no value anywhere are left out
+    pub fn server_headers(&self, server: &McpServer) -> HashMap<String, String> {
+        let (McpServer::Sse {
+            headers: Some(headers),
+            ..
+        }
+        | McpServer::Http {
+            headers: Some(headers),
+            ..
+        }) = server
+        else {
+            return HashMap::new();
+        };
+        headers
+            .keys()
+            .filter_map(|name| Some((name.clone(), self.get(name)?)))
+            .collect()
+    }
+
+    /// `text` with the value of every credential-like var it could contain masked, for logging
+    pub fn redact(&self, text: &str) -> String {
+        let secrets = self
+            .flags
+            .iter()
+            .chain(&self.config)
+            .chain(&self.env_file)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .chain(env::vars())
+            .filter(|(k, v)| looks_secret(k) && v.len() >= 4);
+        secrets.fold(text.to_string(), |text, (_, secret)| {
+            text.replace(&secret, REDACTED)
+        })
+    }
+}
+
+/// Parses `KEY=VALUE` from a `--env` flag
+pub fn parse_env_flag(flag: &str) -> Result<(String, String), String> {
+    match flag.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{flag}'")),
+    }
+}
+
+/// Reads a dotenv file: `KEY=VALUE` lines, optionally `export`ed or quoted, and `#` comments
+fn read_env_file(path: &Path) -> std::io::Result<HashMap<String, String>> {
+    let contents = fs::read_to_string(path)?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = match value.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let quoted = &value[1..];
+                    quoted.split(quote).next().unwrap_or(quoted)
+                }
+                // unquoted values may have a trailing comment
+                _ => value.split(" #").next().unwrap_or_default().trim_end(),
+            };
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(flags: &[(&str, &str)], env_file: &[(&str, &str)]) -> McpEnv {
+        let map = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        McpEnv {
+            flags: map(flags),
+            config: HashMap::new(),
+            env_file: map(env_file),
+        }
+    }
+
+    #[test]
+    fn reads_env_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        fs::write(
+            &path,
+            "# a comment\n\
+             PLAIN=value\n\
+             export EXPORTED=yes\n\
+             DOUBLE=\"quoted # not a comment\" # a comment\n\
+             SINGLE='it''s'\n\
+             COMMENTED=value # a comment\n\
+             HASHED=a#b\n\
+             SPACED = padded \n\
+             EMPTY=\n\
+             not a pair\n",
+        )
+        .unwrap();
+        let vars = read_env_file(&path).unwrap();
+        assert_eq!(
+            vars,
+            HashMap::from(
+                [
+                    ("PLAIN", "value"),
+                    ("EXPORTED", "yes"),
+                    ("DOUBLE", "quoted # not a comment"),
+                    ("SINGLE", "it"),
+                    ("COMMENTED", "value"),
+                    ("HASHED", "a#b"),
+                    ("SPACED", "padded"),
+                    ("EMPTY", ""),
+                ]
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+            )
+        );
+        assert!(read_env_file(&dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn flags_take_precedence_over_env_files() {
+        let env = env(
+            &[("CORALIZER_TEST_A", "flag")],
+            &[("CORALIZER_TEST_A", "file"), ("CORALIZER_TEST_B", "file")],
+        );
+        assert_eq!(env.get("CORALIZER_TEST_A").as_deref(), Some("flag"));
+        assert_eq!(env.get("CORALIZER_TEST_B").as_deref(), Some("file"));
+        assert_eq!(env.get("CORALIZER_TEST_C"), None);
+    }
+
+    #[test]
+    fn redacts_secret_values() {
+        let env = env(
+            &[("CORALIZER_TEST_API_KEY", "sk-12345")],
+            &[
+                ("CORALIZER_TEST_TOKEN", "tok-678"),
+                ("CORALIZER_TEST_PIN_KEY", "123"),
+                ("CORALIZER_TEST_URL", "https://example.com"),
+            ],
+        );
+        assert_eq!(
+            env.redact("key sk-12345, token tok-678, pin 123 at https://example.com"),
+            "key ***, token ***, pin 123 at https://example.com"
+        );
+    }
+
+    #[test]
+    fn parses_env_flags() {
+        assert_eq!(
+            parse_env_flag("KEY=a=b"),
+            Ok(("KEY".to_string(), "a=b".to_string()))
+        );
+        assert_eq!(
+            parse_env_flag("KEY="),
+            Ok(("KEY".to_string(), String::new()))
+        );
+        assert!(parse_env_flag("=value").is_err());
+        assert!(parse_env_flag("KEY").is_err());
+    }
+}
@@ -1,20 +1,62 @@
 use colored::Colorize as _;
 use futures_util::future::join_all;
-use indicatif::ProgressBar;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use itertools::Itertools;
-use rig::client::CompletionClient;
-use rig::completion::Prompt;
-use rig::providers::openai;
-use serde::Deserialize;
+use regex::Regex;
+use rmcp::model::Tool;
+use serde_json::{Map, Value};
 use std::collections::HashMap;
 use std::env;
+use std::fmt::Display;
+use std::path::Path;
+use std::time::Duration;
+use std::{fs, io};
 
-use crate::Runtime;
+use crate::llm::LlmConfig;
 use crate::mcp_client::make_client;
+use crate::mcp_env::McpEnv;
+use crate::oauth::CredentialStore;
+use crate::summary::summarize;
+use crate::tool_cache::ToolCache;
+use crate::{McpKind, Runtime};
 
+/// How listing a server's tools went
+enum Listing {
+    Cached(Vec<Tool>),
+    Live(Vec<Tool>),
+    TimedOut,
+    /// Why, with any secrets redacted
+    Failed(String),
+}
+
+impl Listing {
+    fn outcome(&self, timeout: Duration) -> String {
+        match self {
+            Listing::Cached(tools) => format!("✅ {} tools (cached)", tools.len()),
+            Listing::Live(tools) => format!("✅ {} tools", tools.len()),
+            Listing::TimedOut => format!("⏱️ timed out after {}s", timeout.as_secs())
+                .yellow()
+                .to_string(),
+            Listing::Failed(e) => format!("❌ failed - {e}").red().to_string(),
+        }
+    }
+}
+
+/// Starts `server`, lists its tools and shuts it down again
+async fn list_live(server: &McpServer, env: &McpEnv) -> anyhow::Result<Vec<Tool>> {
+    let client = make_client(server, env)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to connect - {e}"))?;
+    let tools = client.list_all_tools().await;
+    let _ = client.cancel().await;
+    tools.map_err(|e| anyhow::anyhow!("failed to list tools - {e}"))
+}
+
+/// The agent description older versions wrote when `SKIP_LLM` was set, to be filled in by hand.
+pub const PLACEHOLDER_DESCRIPTION: &str = "CHANGE ME";
+
+/// A set of named MCP servers, normalized from any of the common MCP config [`Dialect`]s:
 ///
-/// Follows this:
-/// todo: find an actual spec for this!!!!
 /// ```json
 /// {
 ///   "mcpServers": {
@@ -29,38 +71,490 @@ use crate::mcp_client::make_client;
 ///     }
 ///   }
 /// }```
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct McpServers {
-    #[serde(rename = "mcpServers")]
     pub servers: HashMap<String, McpServer>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-#[serde(tag = "transport")]
+#[derive(Debug, Clone)]
 pub enum McpServer {
-    #[serde(rename = "sse")]
     Sse {
         url: String,
-        #[serde(skip_serializing_if = "Option::is_none")]
         headers: Option<HashMap<String, String>>,
     },
-    #[serde(rename = "http")]
-    #[serde(alias = "streamableHttp")]
     Http {
         url: String,
-        #[serde(skip_serializing_if = "Option::is_none")]
         headers: Option<HashMap<String, String>>,
     },
-    #[serde(untagged)]
     Stdio {
         command: String,
         args: Vec<String>,
-        #[serde(skip_serializing_if = "Option::is_none")]
         env: Option<HashMap<String, String>>,
+        cwd: Option<String>,
+        env_file: Option<String>,
     },
 }
 
+/// The MCP config formats of the various MCP clients out there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    /// `{"mcpServers": {"name": {...}}}` - Claude Desktop, Cursor, Windsurf, Cline & co.
+    McpServers,
+    /// `{"servers": {"name": {"type": ...}}}`, or `{"mcp": {"servers": ...}}` in settings.json -
+    /// VS Code
+    VsCode,
+    /// `{"context_servers": {"name": {...}}}` - Zed
+    Zed,
+    /// `{"mcpServers": [{"name": ...}]}`, or the older
+    /// `{"experimental": {"modelContextProtocolServers": [...]}}` - Continue
+    Continue,
+    /// `[mcp_servers.name]` tables - Codex
+    Codex,
+}
+
+impl Display for Dialect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Dialect::McpServers => "Claude Desktop/Cursor ('mcpServers')",
+            Dialect::VsCode => "VS Code ('servers')",
+            Dialect::Zed => "Zed ('context_servers')",
+            Dialect::Continue => "Continue ('mcpServers' list)",
+            Dialect::Codex => "Codex ('mcp_servers')",
+        })
+    }
+}
+
+impl Dialect {
+    fn detect(root: &Value) -> Option<(Dialect, &Value)> {
+        if let Some(servers) = root.get("mcpServers") {
+            return Some(match servers.is_array() {
+                true => (Dialect::Continue, servers),
+                false => (Dialect::McpServers, servers),
+            });
+        }
+        if let Some(servers) = root
+            .get("servers")
+            .or_else(|| root.get("mcp").and_then(|mcp| mcp.get("servers")))
+        {
+            return Some((Dialect::VsCode, servers));
+        }
+        if let Some(servers) = root.get("context_servers") {
+            return Some((Dialect::Zed, servers));
+        }
+        if let Some(servers) = root.get("mcp_servers") {
+            return Some((Dialect::Codex, servers));
+        }
+        root.get("experimental")
+            .and_then(|e| e.get("modelContextProtocolServers"))
+            .map(|servers| (Dialect::Continue, servers))
+    }
+}
+
+#[derive(Debug)]
+pub struct McpConfigError {
+    pub dialect: Option<Dialect>,
+    pub server: Option<String>,
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl Display for McpConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid MCP config")?;
+        if let Some(dialect) = &self.dialect {
+            write!(f, " (assuming {dialect} format)")?;
+        }
+        if let Some(server) = &self.server {
+            write!(f, " in server '{server}'")?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, " at '{field}'")?;
+        }
+        write!(f, " - {}", self.message)
+    }
+}
+
+impl std::error::Error for McpConfigError {}
+
+/// The file formats an MCP config can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    /// JSON, including JSONC's comments and trailing commas
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Display for ConfigFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConfigFormat::Json => "JSON",
+            ConfigFormat::Toml => "TOML",
+            ConfigFormat::Yaml => "YAML",
+        })
+    }
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" | "jsonc" | "json5" => Some(ConfigFormat::Json),
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format from the first line that isn't blank or a comment.
+    pub fn sniff(contents: &str) -> Self {
+        let toml_key = Regex::new(r#"^("[^"]*"|[\w.-]+)\s*="#).unwrap();
+        let first = contents
+            .lines()
+            .map(str::trim)
+            .find(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with("//"))
+            .unwrap_or_default();
+        if first.starts_with('{') || first.starts_with("/*") {
+            ConfigFormat::Json
+        } else if first.starts_with('[') || toml_key.is_match(first) {
+            ConfigFormat::Toml
+        } else {
+            ConfigFormat::Yaml
+        }
+    }
+
+    fn parse(&self, contents: &str) -> Result<Value, String> {
+        match self {
+            ConfigFormat::Json => json5::from_str(contents).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => toml_edit::de::from_str(contents).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_norway::from_str(contents).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Where in the config we are, for error reporting
+struct Ctx<'a> {
+    dialect: Dialect,
+    server: &'a str,
+}
+
+impl Ctx<'_> {
+    fn err(&self, field: Option<&str>, message: impl Into<String>) -> McpConfigError {
+        McpConfigError {
+            dialect: Some(self.dialect),
+            server: Some(self.server.to_string()),
+            field: field.map(str::to_string),
+            message: message.into(),
+        }
+    }
+
+    fn string(
+        &self,
+        obj: &Map<String, Value>,
+        field: &str,
+    ) -> Result<Option<String>, McpConfigError> {
+        match obj.get(field) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => scalar(value)
+                .map(Some)
+                .ok_or_else(|| self.err(Some(field), format!("expected a string, got {value}"))),
+        }
+    }
+
+    /// A path, relative to the agent's root if it was relative to the VS Code workspace
+    fn path(
+        &self,
+        obj: &Map<String, Value>,
+        field: &str,
+    ) -> Result<Option<String>, McpConfigError> {
+        Ok(self
+            .string(obj, field)?
+            .map(|path| match path.strip_prefix("${workspaceFolder}") {
+                Some(rest) => format!(".{rest}"),
+                None => path,
+            }))
+    }
+
+    fn list(
+        &self,
+        obj: &Map<String, Value>,
+        field: &str,
+    ) -> Result<Option<Vec<String>>, McpConfigError> {
+        match obj.get(field) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::Array(items)) => items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    scalar(item).ok_or_else(|| {
+                        self.err(
+                            Some(&format!("{field}[{i}]")),
+                            format!("expected a string, got {item}"),
+                        )
+                    })
+                })
+                .collect::<Result<_, _>>()
+                .map(Some),
+            Some(value) => Err(self.err(Some(field), format!("expected a list, got {value}"))),
+        }
+    }
+
+    fn map(
+        &self,
+        obj: &Map<String, Value>,
+        field: &str,
+    ) -> Result<Option<HashMap<String, String>>, McpConfigError> {
+        match obj.get(field) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::Object(entries)) => entries
+                .iter()
+                .map(|(k, v)| {
+                    scalar(v).map(|v| (k.clone(), v)).ok_or_else(|| {
+                        self.err(
+                            Some(&format!("{field}.{k}")),
+                            format!("expected a string, got {v}"),
+                        )
+                    })
+                })
+                .collect::<Result<_, _>>()
+                .map(Some),
+            Some(value) => Err(self.err(Some(field), format!("expected an object, got {value}"))),
+        }
+    }
+
+    fn server(&self, entry: &Value) -> Result<McpServer, McpConfigError> {
+        let Some(obj) = entry.as_object() else {
+            return Err(self.err(None, format!("expected an object, got {entry}")));
+        };
+        // Continue's older format nests the whole server under "transport"
+        let obj = match obj.get("transport") {
+            Some(Value::Object(transport)) => transport,
+            _ => obj,
+        };
+
+        // Zed's older format nests the command under "command"
+        let (command, args, env) = match obj.get("command") {
+            Some(Value::Object(command)) => (
+                self.string(command, "path").map_err(|e| McpConfigError {
+                    field: Some("command.path".into()),
+                    ..e
+                })?,
+                self.list(command, "args")?,
+                self.map(command, "env")?,
+            ),
+            _ => (
+                self.string(obj, "command")?,
+                self.list(obj, "args")?,
+                self.map(obj, "env")?,
+            ),
+        };
+        let url = match self.string(obj, "url")? {
+            Some(url) => Some(url),
+            None => self.string(obj, "serverUrl")?,
+        };
+        let headers = match (self.map(obj, "headers")?, obj.get("requestOptions")) {
+            (Some(headers), _) => Some(headers),
+            (None, _) if obj.contains_key("http_headers") => self.map(obj, "http_headers")?,
+            (None, Some(Value::Object(options))) => self.map(options, "headers")?,
+            (None, _) => None,
+        };
+        let (kind_field, kind) = match self.string(obj, "type")? {
+            Some(kind) => ("type", Some(kind)),
+            None => ("transport", self.string(obj, "transport")?),
+        };
+
+        let kind = match kind.as_deref() {
+            Some("stdio") => McpKind::Stdio,
+            Some("sse") => McpKind::Sse,
+            Some("http" | "streamableHttp" | "streamable-http" | "streamable_http") => {
+                McpKind::Http
+            }
+            Some(other) => {
+                return Err(self.err(Some(kind_field), format!("unknown transport '{other}'")));
+            }
+            None if command.is_some() => McpKind::Stdio,
+            // clients that infer the transport go by the conventional endpoint names
+            None if url
+                .as_ref()
+                .is_some_and(|u| u.trim_end_matches('/').ends_with("/sse")) =>
+            {
+                McpKind::Sse
+            }
+            None if url.is_some() => McpKind::Http,
+            None if obj.get("source").and_then(Value::as_str) == Some("extension") => {
+                return Err(self.err(
+                    Some("source"),
+                    "servers provided by an editor extension have no command to run",
+                ));
+            }
+            None => return Err(self.err(None, "expected either a 'command' or a 'url'")),
+        };
+
+        let require_url =
+            || url.ok_or_else(|| self.err(Some("url"), "missing for a remote server"));
+        Ok(match kind {
+            McpKind::Sse => McpServer::Sse {
+                url: require_url()?,
+                headers,
+            },
+            McpKind::Http => McpServer::Http {
+                url: require_url()?,
+                headers,
+            },
+            McpKind::Npx | McpKind::Stdio => McpServer::Stdio {
+                command: command
+                    .ok_or_else(|| self.err(Some("command"), "missing for a stdio server"))?,
+                args: args.unwrap_or_default(),
+                env,
+                cwd: self.path(obj, "cwd")?,
+                env_file: match self.path(obj, "envFile")? {
+                    Some(env_file) => Some(env_file),
+                    None => self.path(obj, "env_file")?,
+                },
+            },
+        })
+    }
+}
+
+/// Strings, numbers & booleans as a string - env values often end up as numbers in YAML/TOML
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+impl McpServers {
+    /// Replaces every env var & header value with the name of the agent option it is read from -
+    /// the var/header name itself. Literal values in the config (e.g. `LOG_LEVEL=info`) are
+    /// returned as the defaults of those options; placeholders and credentials are dropped.
+    pub fn bind_options(&mut self) -> HashMap<String, String> {
+        let mut defaults = HashMap::new();
+        for server in self.servers.values_mut() {
+            let map = match server {
+                McpServer::Http { headers, .. } | McpServer::Sse { headers, .. } => headers,
+                McpServer::Stdio { env, .. } => env,
+            };
+            for (k, v) in map.iter_mut().flatten() {
+                if !is_placeholder(v) && !looks_secret(k) {
+                    defaults.insert(k.clone(), v.clone());
+                }
+                *v = k.clone();
+            }
+        }
+        defaults
+    }
+
+    /// Adds an `Authorization` header to every remote server authorized with `coralizer login`,
+    /// so [`McpServers::bind_options`] makes it an agent option
+    pub fn add_oauth_headers(&mut self, store: &CredentialStore) {
+        for server in self.servers.values_mut() {
+            if let McpServer::Http { url, headers } | McpServer::Sse { url, headers } = server
+                && store.get(url).is_some()
+            {
+                headers
+                    .get_or_insert_default()
+                    .entry("Authorization".to_string())
+                    .or_insert_with(|| "Bearer <token>".to_string());
+            }
+        }
+    }
+
+    /// Reads an MCP config from `path` - or from stdin if `path` is `-` - in any [`ConfigFormat`]
+    pub fn read(path: &Path) -> Result<Self, McpConfigError> {
+        let io_err = |e: io::Error| McpConfigError {
+            dialect: None,
+            server: None,
+            field: None,
+            message: format!("couldn't read '{}' - {e}", path.display()),
+        };
+        let (contents, format) = match path.as_os_str() == "-" {
+            true => (io::read_to_string(io::stdin()).map_err(io_err)?, None),
+            false => (
+                fs::read_to_string(path).map_err(io_err)?,
+                ConfigFormat::from_path(path),
+            ),
+        };
+        Self::parse(
+            &contents,
+            format.unwrap_or_else(|| ConfigFormat::sniff(&contents)),
+        )
+    }
+
+    pub fn parse(contents: &str, format: ConfigFormat) -> Result<Self, McpConfigError> {
+        let value = format.parse(contents).map_err(|e| McpConfigError {
+            dialect: None,
+            server: None,
+            field: None,
+            message: format!("not valid {format} - {e}"),
+        })?;
+        Self::from_value(&value)
+    }
+
+    pub fn from_value(root: &Value) -> Result<Self, McpConfigError> {
+        let Some((dialect, servers)) = Dialect::detect(root) else {
+            return Err(McpConfigError {
+                dialect: None,
+                server: None,
+                field: None,
+                message:
+                    "expected one of 'mcpServers', 'servers', 'context_servers', 'mcp_servers' \
+                    or 'experimental.modelContextProtocolServers' at the top level"
+                        .into(),
+            });
+        };
+        let top_level_err = |message: String| McpConfigError {
+            dialect: Some(dialect),
+            server: None,
+            field: None,
+            message,
+        };
+
+        let entries: Vec<(String, &Value)> = match servers {
+            Value::Object(servers) => servers.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            // Continue lists its servers, names are optional
+            Value::Array(servers) => servers
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let name = v.get("name").and_then(Value::as_str);
+                    (name.map_or_else(|| format!("mcp-{i}"), str::to_string), v)
+                })
+                .collect(),
+            other => return Err(top_level_err(format!("expected servers, got {other}"))),
+        };
+
+        let mut servers = HashMap::new();
+        for (name, entry) in entries {
+            let server = Ctx {
+                dialect,
+                server: &name,
+            }
+            .server(entry)?;
+            if servers.insert(name.clone(), server).is_some() {
+                return Err(top_level_err(format!("duplicate server name '{name}'")));
+            }
+        }
+        Ok(McpServers { servers })
+    }
+}
+
 impl McpServer {
+    /// The npm package an `npx` server runs
+    pub fn npm_package(&self) -> Option<&str> {
+        let Self::Stdio { command, args, .. } = self else {
+            return None;
+        };
+        if !command.trim_end_matches(".cmd").ends_with("npx") {
+            return None;
+        }
+        let package = args.iter().find(|a| !a.starts_with('-'))?;
+        // strip any version, e.g. `@org/server@1.2.3`
+        let name = package.strip_prefix('@').unwrap_or(package);
+        let end = name.find('@').unwrap_or(name.len());
+        (end > 0).then(|| &package[..package.len() - name.len() + end])
+    }
     pub fn runtime(&self) -> Option<Runtime> {
         match self {
             Self::Stdio { command, .. } => {
@@ -84,6 +578,38 @@ impl McpServer {
     }
 }
 
+/// Whether an env var or header called `name` probably holds a credential. Only whole words of
+/// it count - split at `_`, `-` and camelCase humps - so `KEYCLOAK_URL` or `TOKENIZER_PATH` are
+/// kept.
+pub fn looks_secret(name: &str) -> bool {
+    let word_re = Regex::new(r"[A-Z]+[a-z0-9]*|[a-z0-9]+").unwrap();
+    word_re.find_iter(name).any(|word| {
+        let word = word.as_str().to_uppercase();
+        let word = word.strip_suffix('S').unwrap_or(&word);
+        [
+            "KEY",
+            "APIKEY",
+            "TOKEN",
+            "SECRET",
+            "PASSWORD",
+            "AUTHORIZATION",
+            "CREDENTIAL",
+        ]
+        .contains(&word)
+    })
+}
+
+/// Whether a config value is a placeholder for the user to fill in (`<YOUR_KEY>`, `${input:key}`,
+/// ...) rather than an actual setting. Only whole values count, so `https://api.exchange.com` or
+/// `todoist` are kept.
+pub fn is_placeholder(value: &str) -> bool {
+    let placeholder_re = Regex::new(
+        r"(?i)^(|<.*>|\$\{.*\}|\$\w+|your[_-].*|x{3,}|\*{3,}|\.{3}|change[_-]?me|replace[_-]?me|todo|dummy)$",
+    )
+    .unwrap();
+    placeholder_re.is_match(value.trim())
+}
+
 impl From<McpServers> for Vec<McpServer> {
     fn from(val: McpServers) -> Self {
         val.servers.into_values().collect()
@@ -91,50 +617,107 @@ impl From<McpServers> for Vec<McpServer> {
 }
 
 impl McpServers {
-    pub async fn generate_description(&self, pb: ProgressBar) -> String {
-        pb.set_message("Starting MCP server(s)");
-        let mut tools = vec![];
-        let mut clients = vec![];
-        for (name, server) in self.servers.iter() {
-            match make_client(server).await {
-                Ok(client) => {
-                    match client.list_all_tools().await {
-                        Ok(t) => tools.extend(t),
-                        Err(e) => {
-                            eprintln!("Failed to list tools from MCP '{name}' - {e:?}");
+    /// Lists the tools of every server concurrently - from the cache where possible - giving each
+    /// server `timeout` to start and list them. Every server gets a spinner line that ends with
+    /// how it went, and a summary follows. Servers that fail or time out are left out.
+    pub async fn list_tools(
+        &self,
+        cache: &ToolCache,
+        env: &McpEnv,
+        timeout: Duration,
+    ) -> HashMap<String, Vec<Tool>> {
+        let progress = MultiProgress::new();
+        let style = ProgressStyle::with_template("{spinner} {prefix:.bold} {msg}")
+            .expect("valid progress template");
+        let listings =
+            self.servers
+                .iter()
+                .sorted_by_key(|(name, _)| *name)
+                .map(|(name, server)| {
+                    let pb = progress.add(
+                        ProgressBar::new_spinner()
+                            .with_style(style.clone())
+                            .with_prefix(name.clone()),
+                    );
+                    async move {
+                        let listing = match cache.get(server) {
+                            Some(cached) => Listing::Cached(cached),
+                            None => {
+                                pb.enable_steady_tick(Duration::from_millis(100));
+                                pb.set_message("starting...");
+                                match tokio::time::timeout(timeout, list_live(server, env)).await {
+                                    Ok(Ok(tools)) => {
+                                        if let Err(e) = cache.put(server, &tools) {
+                                            pb.suspend(|| {
+                                                eprintln!(
+                                                    "Failed to cache tools of MCP '{name}' - {e}"
+                                                )
+                                            });
+                                        }
+                                        Listing::Live(tools)
+                                    }
+                                    Ok(Err(e)) => Listing::Failed(env.redact(&e.to_string())),
+                                    Err(_) => Listing::TimedOut,
+                                }
+                            }
+                        };
+                        let outcome = listing.outcome(timeout);
+                        // spinner lines aren't drawn when stderr isn't a terminal
+                        if pb.is_hidden() {
+                            eprintln!("{name} {outcome}");
                         }
+                        pb.finish_with_message(outcome);
+                        (name, listing)
                     }
-                    clients.push(client);
-                }
-                Err(e) => {
-                    eprintln!("Failed to connect to '{name}' - {e:?}");
+                });
+        let listings = join_all(listings).await;
+        drop(progress);
+
+        let mut tools = HashMap::new();
+        let (mut ok, mut timed_out, mut failed) = (vec![], vec![], vec![]);
+        for (name, listing) in listings {
+            match listing {
+                Listing::Cached(t) | Listing::Live(t) => {
+                    ok.push(name.as_str());
+                    tools.insert(name.clone(), t);
                 }
+                Listing::TimedOut => timed_out.push(name.as_str()),
+                Listing::Failed(_) => failed.push(name.as_str()),
             }
         }
-        let close_all = clients.into_iter().map(|client| client.cancel());
-        let close_all = tokio::spawn(join_all(close_all));
-
-        println!("✅ {}", format!("Found {} tools.", tools.len()).green());
+        let summary = [(ok, "listed"), (timed_out, "timed out"), (failed, "failed")]
+            .into_iter()
+            .filter(|(names, _)| !names.is_empty())
+            .map(|(names, outcome)| format!("{} {outcome} ({})", names.len(), names.join(", ")))
+            .join(", ");
+        if !summary.is_empty() {
+            eprintln!("MCP servers: {summary}");
+        }
+        tools
+    }
 
-        let openai_client = openai::Client::from_env();
+    pub async fn generate_description(
+        &self,
+        tools: &HashMap<String, Vec<Tool>>,
+        llm: &LlmConfig,
+        pb: ProgressBar,
+    ) -> String {
+        let tool_str = tools
+            .values()
+            .flatten()
+            .flat_map(serde_json::to_string)
+            .join("\n\n");
 
-        let tool_str = tools.iter().flat_map(serde_json::to_string).join("\n\n");
+        if llm.disabled || env::var("SKIP_LLM").is_ok() {
+            return summarize(tools);
+        }
 
-        let response = match env::var("SKIP_LLM").is_ok() {
-            true => {
-                // TODO: ask for description
-                String::from("CHANGE ME")
-            }
-            false => {
-                let gpt4 = openai_client
-                    .agent("gpt-4")
-                    .preamble("You are a helpful assistant.")
-                    .build();
-
-                pb.set_message("Generating agent description...");
-                gpt4
-                    .prompt(
-                        format!(r#"
+        pb.set_message(format!(
+            "Generating agent description with {}...",
+            llm.provider()
+        ));
+        let prompt = format!(
+            r#"
 We are making an agent with access to the the following tooling:
 # start of tooling
 {tool_str}
@@ -143,13 +726,346 @@ We are making an agent with access to the the following tooling:
 This agent is being generated around this tooling to represent its capabilities and responsibilities as an agent to other agents.
 Other agents, as well as human developers will use the agent's description to determine whether it is relevant to communicate with and use.
 
-With these tools in mind, generate a short description (10 - 50 words) that describes the agent's capabilities and responsibilities."#).trim())
-                    .await
-                    .expect("Failed to prompt GPT-4")
+With these tools in mind, generate a short description (10 - 50 words) that describes the agent's capabilities and responsibilities."#
+        );
+        match llm
+            .prompt("You are a helpful assistant.", prompt.trim())
+            .await
+        {
+            Ok(description) => description,
+            Err(e) => {
+                pb.suspend(|| {
+                    eprintln!(
+                        "{}",
+                        format!(
+                            "⚠️: could not generate a description with {}, summarizing the tools instead - {e}",
+                            llm.provider()
+                        )
+                        .yellow()
+                    )
+                });
+                summarize(tools)
             }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(config: Value) -> McpServers {
+        McpServers::from_value(&config).unwrap()
+    }
+
+    fn stdio(servers: &McpServers, name: &str) -> (String, Vec<String>, Option<String>) {
+        match &servers.servers[name] {
+            McpServer::Stdio {
+                command, args, cwd, ..
+            } => (command.clone(), args.clone(), cwd.clone()),
+            other => panic!("expected a stdio server, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn claude_desktop() {
+        let servers = parse(json!({"mcpServers": {
+            "fs": {"command": "npx", "args": ["-y", "server-fs"], "env": {"ROOT": "/tmp"}},
+            "remote": {"url": "https://example.com/sse/"},
+            "api": {"serverUrl": "https://example.com/mcp"},
+        }}));
+        assert_eq!(
+            stdio(&servers, "fs"),
+            ("npx".into(), vec!["-y".into(), "server-fs".into()], None)
+        );
+        assert!(matches!(servers.servers["remote"], McpServer::Sse { .. }));
+        assert!(matches!(servers.servers["api"], McpServer::Http { .. }));
+    }
+
+    #[test]
+    fn vs_code() {
+        let servers = parse(json!({"servers": {
+            "local": {
+                "type": "stdio",
+                "command": "server",
+                "cwd": "${workspaceFolder}/tools",
+                "envFile": "${workspaceFolder}/.env",
+            },
+            "remote": {"type": "http", "url": "https://example.com", "headers": {"X-Key": "k"}},
+        }}));
+        let McpServer::Stdio { cwd, env_file, .. } = &servers.servers["local"] else {
+            panic!("expected a stdio server");
+        };
+        assert_eq!(cwd.as_deref(), Some("./tools"));
+        assert_eq!(env_file.as_deref(), Some("./.env"));
+        let McpServer::Http { headers, .. } = &servers.servers["remote"] else {
+            panic!("expected an http server");
+        };
+        assert_eq!(headers.as_ref().unwrap()["X-Key"], "k");
+
+        let settings = parse(json!({"mcp": {"servers": {"s": {"command": "server"}}}}));
+        assert_eq!(stdio(&settings, "s").0, "server");
+    }
+
+    #[test]
+    fn zed() {
+        let servers = parse(json!({"context_servers": {
+            "old": {"command": {"path": "server", "args": ["--x"], "env": {"A": 1}}},
+            "new": {"command": "server", "args": []},
+        }}));
+        assert_eq!(
+            stdio(&servers, "old"),
+            ("server".into(), vec!["--x".into()], None)
+        );
+        let McpServer::Stdio { env, .. } = &servers.servers["old"] else {
+            panic!("expected a stdio server");
+        };
+        assert_eq!(env.as_ref().unwrap()["A"], "1");
+        assert_eq!(stdio(&servers, "new").0, "server");
+    }
+
+    #[test]
+    fn continue_lists() {
+        let servers = parse(json!({"mcpServers": [
+            {"name": "named", "command": "server"},
+            {"command": "other"},
+        ]}));
+        assert_eq!(stdio(&servers, "named").0, "server");
+        assert_eq!(stdio(&servers, "mcp-1").0, "other");
+
+        let older = parse(json!({"experimental": {"modelContextProtocolServers": [
+            {"transport": {"type": "stdio", "command": "server"}},
+        ]}}));
+        assert_eq!(stdio(&older, "mcp-0").0, "server");
+    }
+
+    #[test]
+    fn errors_say_where() {
+        let unknown = McpServers::from_value(&json!({"tools": {}})).unwrap_err();
+        assert_eq!(unknown.dialect, None);
+
+        let transport = McpServers::from_value(&json!({"servers": {
+            "s": {"type": "carrier-pigeon", "command": "coo"},
+        }}))
+        .unwrap_err();
+        assert_eq!(transport.dialect, Some(Dialect::VsCode));
+        assert_eq!(transport.server.as_deref(), Some("s"));
+        assert_eq!(transport.field.as_deref(), Some("type"));
+
+        let duplicate = McpServers::from_value(&json!({"mcpServers": [
+            {"name": "s", "command": "a"},
+            {"name": "s", "command": "b"},
+        ]}))
+        .unwrap_err();
+        assert!(duplicate.message.contains("duplicate server name 's'"));
+
+        let missing = McpServers::from_value(&json!({"mcpServers": {"s": {}}})).unwrap_err();
+        assert!(
+            missing
+                .to_string()
+                .contains("expected either a 'command' or a 'url'")
+        );
+    }
+
+    #[test]
+    fn sniffs_the_format() {
+        assert_eq!(ConfigFormat::sniff("// servers\n{}"), ConfigFormat::Json);
+        assert_eq!(ConfigFormat::sniff("/* servers */ {}"), ConfigFormat::Json);
+        assert_eq!(
+            ConfigFormat::sniff("# servers\n[mcp_servers.s]"),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::sniff("\"quoted.key\" = 1"),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::sniff("mcp-servers.s.command = 'x'"),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::sniff("# servers\nmcpServers:"),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(ConfigFormat::sniff(""), ConfigFormat::Yaml);
+
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("mcp.jsonc")),
+            Some(ConfigFormat::Json)
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("mcp.yml")),
+            Some(ConfigFormat::Yaml)
+        );
+        assert_eq!(ConfigFormat::from_path(Path::new("mcp")), None);
+    }
+
+    #[test]
+    fn parses_jsonc() {
+        let config = r#"{
+            // the servers
+            "mcpServers": {
+                "s": {"command": "server", "args": ["a",],},
+            },
+        }"#;
+        let servers = McpServers::parse(config, ConfigFormat::Json).unwrap();
+        assert_eq!(
+            stdio(&servers, "s"),
+            ("server".into(), vec!["a".into()], None)
+        );
+    }
+
+    #[test]
+    fn parses_codex_toml() {
+        let config = r#"
+            [mcp_servers.local]
+            command = "server"
+            env = { PORT = 8080 }
+
+            [mcp_servers.remote]
+            url = "https://example.com/mcp"
+            http_headers = { "X-Key" = "k" }
+        "#;
+        let servers = McpServers::parse(config, ConfigFormat::Toml).unwrap();
+        let McpServer::Stdio { env, .. } = &servers.servers["local"] else {
+            panic!("expected a stdio server");
         };
+        assert_eq!(env.as_ref().unwrap()["PORT"], "8080");
+        let McpServer::Http { headers, .. } = &servers.servers["remote"] else {
+            panic!("expected an http server");
+        };
+        assert_eq!(headers.as_ref().unwrap()["X-Key"], "k");
+    }
+
+    #[test]
+    fn parses_yaml() {
+        let config = "mcpServers:\n  s:\n    command: server\n    env:\n      DEBUG: true\n";
+        let servers = McpServers::parse(config, ConfigFormat::Yaml).unwrap();
+        let McpServer::Stdio { env, .. } = &servers.servers["s"] else {
+            panic!("expected a stdio server");
+        };
+        assert_eq!(env.as_ref().unwrap()["DEBUG"], "true");
+    }
+
+    #[test]
+    fn names_the_format_it_failed_to_parse() {
+        let e = McpServers::parse("{\"mcpServers\": ", ConfigFormat::Json).unwrap_err();
+        assert!(e.message.starts_with("not valid JSON - "));
+    }
+
+    #[test]
+    fn placeholders_are_whole_values() {
+        for placeholder in [
+            "",
+            "<your api key>",
+            "${input:token}",
+            "$API_KEY",
+            "YOUR_API_KEY",
+            "your-token-here",
+            "xxxxxxxx",
+            "...",
+            "CHANGE_ME",
+            "todo",
+        ] {
+            assert!(is_placeholder(placeholder), "{placeholder:?}");
+        }
+        for value in [
+            "https://api.exchange.com",
+            "todoist",
+            "yourls",
+            "replace-prefix",
+            "info",
+            "8080",
+            "a<b",
+        ] {
+            assert!(!is_placeholder(value), "{value:?}");
+        }
+    }
+
+    fn npx(args: &[&str]) -> McpServer {
+        McpServer::Stdio {
+            command: "npx".to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            env: None,
+            cwd: None,
+            env_file: None,
+        }
+    }
+
+    #[test]
+    fn npm_packages_lose_their_version() {
+        assert_eq!(npx(&["-y", "server@1.2.3"]).npm_package(), Some("server"));
+        assert_eq!(
+            npx(&["-y", "@org/server@latest", "--port", "1"]).npm_package(),
+            Some("@org/server")
+        );
+        assert_eq!(npx(&["@org/server"]).npm_package(), Some("@org/server"));
+        assert_eq!(npx(&["ünïcode@1"]).npm_package(), Some("ünïcode"));
+        assert_eq!(npx(&[""]).npm_package(), None);
+        assert_eq!(npx(&["@"]).npm_package(), None);
+        assert_eq!(npx(&["-y"]).npm_package(), None);
+
+        let node = McpServer::Stdio {
+            command: "node".to_string(),
+            args: vec!["server".to_string()],
+            env: None,
+            cwd: None,
+            env_file: None,
+        };
+        assert_eq!(node.npm_package(), None);
+    }
+
+    #[test]
+    fn secrets_go_by_name() {
+        for name in [
+            "API_KEY",
+            "github_token",
+            "Authorization",
+            "DB_PASSWORD",
+            "X-Api-Key",
+            "apiKey",
+            "OPENAI_APIKEY",
+            "accessToken",
+            "GOOGLE_APPLICATION_CREDENTIALS",
+        ] {
+            assert!(looks_secret(name), "{name}");
+        }
+        for name in [
+            "LOG_LEVEL",
+            "PORT",
+            "BASE_URL",
+            "KEYCLOAK_URL",
+            "MONKEY_MODE",
+            "TOKENIZER_PATH",
+            "SECRETARY",
+            "keyboardLayout",
+        ] {
+            assert!(!looks_secret(name), "{name}");
+        }
+    }
+
+    #[tokio::test]
+    async fn leaves_out_hanging_and_failing_servers() {
+        let servers = parse(json!({"mcpServers": {
+            "hanging": {"command": "sleep", "args": ["30"]},
+            "failing": {"command": "false"},
+        }}));
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ToolCache::in_dir(dir.path().to_path_buf(), false);
+        let timeout = Duration::from_millis(500);
 
-        let _ = close_all.await;
-        response
+        let started = std::time::Instant::now();
+        let tools = servers
+            .list_tools(&cache, &McpEnv::default(), timeout)
+            .await;
+        let elapsed = started.elapsed();
+        assert!(
+            elapsed < timeout + Duration::from_secs(2),
+            "took {elapsed:?}"
+        );
+        assert!(tools.is_empty(), "{tools:?}");
+        // nothing is cached for them either
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
     }
 }
@@ -0,0 +1,733 @@
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context as _, anyhow, bail};
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rand::Rng as _;
+use rand::distr::Alphanumeric;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::TcpListener;
+use url::Url;
+
+/// How long to wait for the user to finish authorizing in their browser
+const AUTHORIZE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Tokens from authorizing coralizer with a remote MCP server through the MCP authorization flow
+/// (OAuth 2.1 with PKCE), along with what's needed to refresh them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credentials {
+    pub server_url: String,
+    pub client_id: String,
+    client_secret: Option<String>,
+    token_endpoint: String,
+    access_token: String,
+    refresh_token: Option<String>,
+    /// Unix time the access token expires at, if it does
+    expires_at: Option<u64>,
+}
+
+impl Credentials {
+    fn is_expired(&self) -> bool {
+        // refresh a little early rather than have a listing fail halfway
+        self.expires_at.is_some_and(|at| now() + 30 >= at)
+    }
+}
+
+/// Credentials kept in a dir only the user can read, one file per server URL
+pub struct CredentialStore {
+    dir: PathBuf,
+}
+
+impl CredentialStore {
+    pub fn new(dir: PathBuf) -> Self {
+        CredentialStore { dir }
+    }
+
+    /// The store in coralizer's config dir
+    pub fn in_config_dir() -> anyhow::Result<Self> {
+        let dirs = directories_next::ProjectDirs::from("com", "coral-protocol", "coralizer")
+            .context("couldn't find a config dir for coralizer")?;
+        Ok(Self::new(dirs.config_dir().join("oauth")))
+    }
+
+    fn path(&self, server_url: &str) -> PathBuf {
+        let key: String = Sha256::digest(server_url)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        self.dir.join(format!("{key}.json"))
+    }
+
+    pub fn get(&self, server_url: &str) -> Option<Credentials> {
+        let contents = fs::read_to_string(self.path(server_url)).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    /// Tokens are as good as passwords, so they're never readable by anyone else - not even for
+    /// a moment: they're written to a new file created private, then renamed over the old one.
+    pub fn put(&self, credentials: &Credentials) -> io::Result<()> {
+        let mut dir = fs::DirBuilder::new();
+        dir.recursive(true);
+        let mut file = fs::OpenOptions::new();
+        file.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{DirBuilderExt as _, OpenOptionsExt as _, PermissionsExt as _};
+            dir.mode(0o700);
+            file.mode(0o600);
+            dir.create(&self.dir)?;
+            // in case it was made by an older coralizer
+            fs::set_permissions(&self.dir, fs::Permissions::from_mode(0o700))?;
+        }
+        #[cfg(not(unix))]
+        dir.create(&self.dir)?;
+
+        let path = self.path(&credentials.server_url);
+        let temp = path.with_extension(format!("{}.tmp", random_string(8)));
+        let written = file.open(&temp).and_then(|mut file| {
+            file.write_all(serde_json::to_string_pretty(credentials)?.as_bytes())?;
+            file.sync_all()
+        });
+        match written.and_then(|()| fs::rename(&temp, &path)) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns whether there were credentials to remove
+    pub fn remove(&self, server_url: &str) -> io::Result<bool> {
+        match fs::remove_file(self.path(server_url)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The access token coralizer was given for `server_url`, refreshed (and stored again) if it has
+/// expired. `None` if the server was never authorized, or the token can't be refreshed.
+pub async fn access_token(store: &CredentialStore, server_url: &str) -> Option<String> {
+    let mut credentials = store.get(server_url)?;
+    if credentials.is_expired() {
+        let refresh_token = credentials.refresh_token.clone()?;
+        let mut form = vec![
+            ("grant_type", "refresh_token".to_string()),
+            ("refresh_token", refresh_token),
+            ("client_id", credentials.client_id.clone()),
+            ("resource", server_url.to_string()),
+        ];
+        if let Some(secret) = &credentials.client_secret {
+            form.push(("client_secret", secret.clone()));
+        }
+        let tokens = request_tokens(&credentials.token_endpoint, &form)
+            .await
+            .ok()?;
+        credentials.access_token = tokens.access_token;
+        credentials.expires_at = tokens.expires_in.map(|secs| now() + secs);
+        if tokens.refresh_token.is_some() {
+            credentials.refresh_token = tokens.refresh_token;
+        }
+        let _ = store.put(&credentials);
+    }
+    Some(credentials.access_token)
+}
+
+/// Where a resource's authorization server says to send the user and their code
+#[derive(Debug, Deserialize)]
+struct AuthServerMetadata {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    registration_endpoint: Option<String>,
+    #[serde(default)]
+    scopes_supported: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProtectedResourceMetadata {
+    #[serde(default)]
+    authorization_servers: Vec<String>,
+    #[serde(default)]
+    scopes_supported: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientRegistration {
+    client_id: String,
+    client_secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+/// Runs the MCP authorization flow for the server at `server_url`:
+///
+/// 1. discovers its authorization server from its protected resource metadata (RFC 9728),
+///    falling back to the server's own origin, and that server's metadata (RFC 8414)
+/// 2. registers coralizer as a client (RFC 7591), unless `client_id` is given
+/// 3. sends the user to authorize in their browser with PKCE, receiving the code on a loopback
+///    redirect
+/// 4. exchanges the code for tokens
+///
+/// `open` is given the URL the user needs to visit, and should e.g. open a browser at it.
+pub async fn authorize(
+    server_url: &str,
+    client_id: Option<String>,
+    open: impl FnOnce(&Url),
+) -> anyhow::Result<Credentials> {
+    let http = reqwest::Client::new();
+    let server = Url::parse(server_url).context("invalid server URL")?;
+
+    let resource = discover_resource(&http, &server).await;
+    let issuer = match resource
+        .as_ref()
+        .and_then(|r| r.authorization_servers.first())
+    {
+        Some(issuer) => Url::parse(issuer).context("invalid authorization server URL")?,
+        None => origin(&server),
+    };
+    let metadata = discover_auth_server(&http, &issuer).await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let redirect_uri = format!(
+        "http://127.0.0.1:{}/callback",
+        listener.local_addr()?.port()
+    );
+
+    let (client_id, client_secret) = match (client_id, &metadata.registration_endpoint) {
+        (Some(client_id), _) => (client_id, None),
+        (None, Some(endpoint)) => {
+            let registration = register(&http, endpoint, &redirect_uri).await?;
+            (registration.client_id, registration.client_secret)
+        }
+        (None, None) => bail!(
+            "the authorization server doesn't support dynamic client registration - pass a \
+             `--client-id` registered with it"
+        ),
+    };
+
+    let verifier = random_string(64);
+    let challenge = code_challenge(&verifier);
+    let state = random_string(32);
+    let scopes = match resource.as_ref().map(|r| &r.scopes_supported) {
+        Some(scopes) if !scopes.is_empty() => scopes.clone(),
+        _ => metadata.scopes_supported.clone(),
+    };
+
+    let mut authorize_url =
+        Url::parse(&metadata.authorization_endpoint).context("invalid authorization endpoint")?;
+    {
+        let mut query = authorize_url.query_pairs_mut();
+        query
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &client_id)
+            .append_pair("redirect_uri", &redirect_uri)
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256")
+            .append_pair("state", &state)
+            .append_pair("resource", server_url);
+        if !scopes.is_empty() {
+            query.append_pair("scope", &scopes.join(" "));
+        }
+    }
+    open(&authorize_url);
+
+    let code = tokio::time::timeout(AUTHORIZE_TIMEOUT, receive_code(&listener, &state))
+        .await
+        .map_err(|_| anyhow!("timed out waiting for authorization"))??;
+
+    let mut form = vec![
+        ("grant_type", "authorization_code".to_string()),
+        ("code", code),
+        ("redirect_uri", redirect_uri),
+        ("client_id", client_id.clone()),
+        ("code_verifier", verifier),
+        ("resource", server_url.to_string()),
+    ];
+    if let Some(secret) = &client_secret {
+        form.push(("client_secret", secret.clone()));
+    }
+    let tokens = request_tokens(&metadata.token_endpoint, &form).await?;
+
+    Ok(Credentials {
+        server_url: server_url.to_string(),
+        client_id,
+        client_secret,
+        token_endpoint: metadata.token_endpoint,
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        expires_at: tokens.expires_in.map(|secs| now() + secs),
+    })
+}
+
+/// The server's protected resource metadata, at the path-specific well-known URL or the root one
+async fn discover_resource(
+    http: &reqwest::Client,
+    server: &Url,
+) -> Option<ProtectedResourceMetadata> {
+    let path = server.path().trim_end_matches('/');
+    let mut urls = vec![];
+    if !path.is_empty() {
+        urls.push(well_known(server, "oauth-protected-resource", path));
+    }
+    urls.push(well_known(server, "oauth-protected-resource", ""));
+    for url in urls {
+        if let Ok(metadata) = get_json(http, url).await {
+            return Some(metadata);
+        }
+    }
+    None
+}
+
+/// The authorization server's metadata, from any of the well-known URLs it may be at - or, for
+/// servers that predate discovery, the default endpoints at its root
+async fn discover_auth_server(http: &reqwest::Client, issuer: &Url) -> AuthServerMetadata {
+    let path = issuer.path().trim_end_matches('/');
+    let mut urls = vec![
+        well_known(issuer, "oauth-authorization-server", path),
+        well_known(issuer, "openid-configuration", path),
+    ];
+    if !path.is_empty()
+        && let Ok(url) = Url::parse(&format!(
+            "{}/.well-known/openid-configuration",
+            issuer.as_str().trim_end_matches('/')
+        ))
+    {
+        urls.push(url);
+    }
+    for url in urls {
+        if let Ok(metadata) = get_json(http, url).await {
+            return metadata;
+        }
+    }
+    let root = origin(issuer);
+    let endpoint = |path: &str| root.join(path).map(String::from).unwrap_or_default();
+    AuthServerMetadata {
+        authorization_endpoint: endpoint("/authorize"),
+        token_endpoint: endpoint("/token"),
+        registration_endpoint: Some(endpoint("/register")),
+        scopes_supported: vec![],
+    }
+}
+
+async fn register(
+    http: &reqwest::Client,
+    endpoint: &str,
+    redirect_uri: &str,
+) -> anyhow::Result<ClientRegistration> {
+    let response = http
+        .post(endpoint)
+        .json(&serde_json::json!({
+            "client_name": env!("CARGO_PKG_NAME"),
+            "redirect_uris": [redirect_uri],
+            "grant_types": ["authorization_code", "refresh_token"],
+            "response_types": ["code"],
+            "token_endpoint_auth_method": "none",
+        }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        bail!(
+            "client registration failed ({}) - {}",
+            response.status(),
+            response.text().await.unwrap_or_default()
+        );
+    }
+    Ok(response.json().await?)
+}
+
+async fn request_tokens(
+    token_endpoint: &str,
+    form: &[(&str, String)],
+) -> anyhow::Result<TokenResponse> {
+    let response = reqwest::Client::new()
+        .post(token_endpoint)
+        .form(form)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        bail!(
+            "token request failed ({}) - {}",
+            response.status(),
+            response.text().await.unwrap_or_default()
+        );
+    }
+    Ok(response.json().await?)
+}
+
+/// Waits for the browser to be redirected back to `listener`, returning the authorization code
+async fn receive_code(listener: &TcpListener, state: &str) -> anyhow::Result<String> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let mut request = vec![];
+        let mut buf = [0; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let read = stream.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..read]);
+        }
+        let request = String::from_utf8_lossy(&request);
+        let target = request.split_whitespace().nth(1).unwrap_or_default();
+        let url = Url::parse("http://127.0.0.1")?.join(target)?;
+        // browsers ask for a favicon too
+        if url.path() != "/callback" {
+            let _ = stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                .await;
+            continue;
+        }
+
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+        let result = match (param("error"), param("code")) {
+            (Some(error), _) => Err(anyhow!(
+                "authorization was denied - {error} {}",
+                param("error_description").unwrap_or_default()
+            )),
+            _ if param("state").as_deref() != Some(state) => {
+                Err(anyhow!("authorization response had the wrong state"))
+            }
+            (None, Some(code)) => Ok(code),
+            (None, None) => Err(anyhow!("authorization response had no code")),
+        };
+        let body = match &result {
+            Ok(_) => "Authorized coralizer - you can close this tab.".to_string(),
+            Err(e) => format!("Authorizing coralizer failed - {e}"),
+        };
+        let _ = stream
+            .write_all(
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .as_bytes(),
+            )
+            .await;
+        return result;
+    }
+}
+
+async fn get_json<T: for<'de> Deserialize<'de>>(
+    http: &reqwest::Client,
+    url: Url,
+) -> anyhow::Result<T> {
+    Ok(http
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+/// `https://host/.well-known/<name><path>`
+fn well_known(url: &Url, name: &str, path: &str) -> Url {
+    let mut url = origin(url);
+    url.set_path(&format!("/.well-known/{name}{path}"));
+    url
+}
+
+fn origin(url: &Url) -> Url {
+    let mut origin = url.clone();
+    origin.set_path("/");
+    origin.set_query(None);
+    origin.set_fragment(None);
+    origin
+}
+
+/// The PKCE `S256` challenge for `verifier` (RFC 7636)
+fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier))
+}
+
+fn random_string(len: usize) -> String {
+    rand::rng()
+        .sample_iter(Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    fn query(url: &Url, name: &str) -> String {
+        url.query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.into_owned())
+            .unwrap_or_else(|| panic!("no {name} in {url}"))
+    }
+
+    /// Stands in for the browser: sends the redirect the authorization server would
+    fn approve(authorize_url: &Url, code: &str) {
+        let mut redirect = Url::parse(&query(authorize_url, "redirect_uri")).unwrap();
+        redirect
+            .query_pairs_mut()
+            .append_pair("code", code)
+            .append_pair("state", &query(authorize_url, "state"));
+        tokio::spawn(reqwest::get(redirect));
+    }
+
+    fn credentials(server_url: &str, token_endpoint: &str, expires_at: u64) -> Credentials {
+        Credentials {
+            server_url: server_url.to_string(),
+            client_id: "client".to_string(),
+            client_secret: None,
+            token_endpoint: token_endpoint.to_string(),
+            access_token: "old-token".to_string(),
+            refresh_token: Some("refresh-token".to_string()),
+            expires_at: Some(expires_at),
+        }
+    }
+
+    #[test]
+    fn pkce_challenge() {
+        // RFC 7636 appendix B
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[tokio::test]
+    async fn authorizes_with_discovery_and_registration() {
+        let server = MockServer::start().await;
+        let issuer = format!("{}/auth", server.uri());
+        Mock::given(method("GET"))
+            .and(path("/.well-known/oauth-protected-resource/mcp"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "resource": format!("{}/mcp", server.uri()),
+                "authorization_servers": [issuer],
+                "scopes_supported": ["tools"],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/.well-known/oauth-authorization-server/auth"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "issuer": issuer,
+                "authorization_endpoint": format!("{issuer}/authorize"),
+                "token_endpoint": format!("{issuer}/token"),
+                "registration_endpoint": format!("{issuer}/register"),
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/auth/register"))
+            .and(body_string_contains(
+                "\"token_endpoint_auth_method\":\"none\"",
+            ))
+            .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({
+                "client_id": "registered-client",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/auth/token"))
+            .and(body_string_contains("grant_type=authorization_code"))
+            .and(body_string_contains("code=the-code"))
+            .and(body_string_contains("client_id=registered-client"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "access-token",
+                "token_type": "Bearer",
+                "refresh_token": "refresh-token",
+                "expires_in": 3600,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let server_url = format!("{}/mcp", server.uri());
+        let mut authorize_url = None;
+        let credentials = authorize(&server_url, None, |url| {
+            approve(url, "the-code");
+            authorize_url = Some(url.clone());
+        })
+        .await
+        .unwrap();
+
+        let authorize_url = authorize_url.unwrap();
+        assert!(
+            authorize_url
+                .as_str()
+                .starts_with(&format!("{issuer}/authorize?"))
+        );
+        assert_eq!(query(&authorize_url, "client_id"), "registered-client");
+        assert_eq!(query(&authorize_url, "code_challenge_method"), "S256");
+        assert_eq!(query(&authorize_url, "resource"), server_url);
+        assert_eq!(query(&authorize_url, "scope"), "tools");
+
+        // the verifier sent with the code must be the one the challenge was made from
+        let requests = server.received_requests().await.unwrap();
+        let token_request = requests
+            .iter()
+            .find(|request| request.url.path() == "/auth/token")
+            .unwrap();
+        let (_, verifier) = url::form_urlencoded::parse(&token_request.body)
+            .find(|(k, _)| k == "code_verifier")
+            .unwrap();
+        assert_eq!(
+            code_challenge(&verifier),
+            query(&authorize_url, "code_challenge")
+        );
+
+        assert_eq!(credentials.client_id, "registered-client");
+        assert_eq!(credentials.token_endpoint, format!("{issuer}/token"));
+        assert_eq!(credentials.access_token, "access-token");
+        assert_eq!(credentials.refresh_token.as_deref(), Some("refresh-token"));
+        assert!(!credentials.is_expired());
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_server_origin() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/register"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(body_string_contains("client_id=given-client"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "access-token",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let credentials = authorize(
+            &format!("{}/mcp", server.uri()),
+            Some("given-client".to_string()),
+            |url| {
+                assert_eq!(url.path(), "/authorize");
+                approve(url, "the-code");
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(credentials.access_token, "access-token");
+        assert_eq!(credentials.expires_at, None);
+    }
+
+    #[tokio::test]
+    async fn fails_when_registration_is_refused() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/register"))
+            .respond_with(ResponseTemplate::new(400).set_body_string("invalid_redirect_uri"))
+            .mount(&server)
+            .await;
+
+        let e = authorize(&server.uri(), None, |_| panic!("shouldn't authorize"))
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("invalid_redirect_uri"), "{e}");
+    }
+
+    #[tokio::test]
+    async fn refreshes_expired_tokens() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(body_string_contains("grant_type=refresh_token"))
+            .and(body_string_contains("refresh_token=refresh-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "new-token",
+                "expires_in": 3600,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let store = CredentialStore::new(dir.path().to_path_buf());
+        let token_endpoint = format!("{}/token", server.uri());
+
+        let fresh = "https://fresh.example.com/mcp";
+        store
+            .put(&credentials(fresh, &token_endpoint, now() + 3600))
+            .unwrap();
+        assert_eq!(
+            access_token(&store, fresh).await.as_deref(),
+            Some("old-token")
+        );
+
+        let expired = "https://expired.example.com/mcp";
+        store
+            .put(&credentials(expired, &token_endpoint, now()))
+            .unwrap();
+        assert_eq!(
+            access_token(&store, expired).await.as_deref(),
+            Some("new-token")
+        );
+        let stored = store.get(expired).unwrap();
+        assert_eq!(stored.access_token, "new-token");
+        // the server didn't rotate it
+        assert_eq!(stored.refresh_token.as_deref(), Some("refresh-token"));
+
+        assert_eq!(
+            access_token(&store, "https://unknown.example.com").await,
+            None
+        );
+    }
+
+    #[test]
+    fn stores_credentials_privately() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CredentialStore::new(dir.path().join("oauth"));
+        let url = "https://example.com/mcp";
+        store
+            .put(&credentials(url, "https://example.com/token", now()))
+            .unwrap();
+        store
+            .put(&credentials(url, "https://example.com/token2", now()))
+            .unwrap();
+
+        assert_eq!(
+            store.get(url).unwrap().token_endpoint,
+            "https://example.com/token2"
+        );
+        let files = fs::read_dir(&store.dir).unwrap().count();
+        assert_eq!(files, 1, "temp files are renamed into place");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            let mode = |path: &std::path::Path| fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode(&store.dir) & 0o777, 0o700);
+            assert_eq!(mode(&store.path(url)) & 0o777, 0o600);
+        }
+
+        assert!(store.remove(url).unwrap());
+        assert!(!store.remove(url).unwrap());
+        assert!(store.get(url).is_none());
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use itertools::Itertools;
+use regex::Regex;
+use rmcp::model::Tool;
+
+use crate::agent_config::{AgentOption, OptionType};
+use crate::mcp_server::{McpServer, McpServers, looks_secret};
+
+/// An agent option used by one or more MCP servers, with the metadata we could infer for it.
+#[derive(Debug, Clone)]
+pub struct InferredOption {
+    pub kind: OptionType,
+    pub default: Option<String>,
+    pub description: String,
+    pub secret: bool,
+}
+
+impl InferredOption {
+    /// The option as written to `coral-agent.toml` - either required or with a default, never both
+    pub fn to_option(&self) -> AgentOption {
+        AgentOption {
+            kind: self.kind,
+            required: self.default.is_none().then_some(true),
+            description: Some(self.description.clone()),
+            default: self.default.as_deref().map(|d| self.kind.value(d)),
+            secret: self.secret,
+        }
+    }
+}
+
+/// Infers the options used by `mcps` from the config's literal `defaults` (see
+/// [`McpServers::bind_options`]), the servers' tools and, for npx servers, their npm README
+/// (unless `offline`).
+pub async fn infer_options(
+    mcps: &McpServers,
+    defaults: &HashMap<String, String>,
+    tools: &HashMap<String, Vec<Tool>>,
+    offline: bool,
+) -> BTreeMap<String, InferredOption> {
+    let mut options: BTreeMap<String, InferredOption> = BTreeMap::new();
+    for (server_name, server) in mcps.servers.iter().sorted_by_key(|(name, _)| *name) {
+        let (map, what) = match server {
+            McpServer::Stdio { env, .. } => (env, "env var"),
+            McpServer::Http { headers, .. } | McpServer::Sse { headers, .. } => (headers, "header"),
+        };
+        let Some(map) = map.as_ref().filter(|map| !map.is_empty()) else {
+            continue;
+        };
+
+        let mut docs = tools
+            .get(server_name)
+            .into_iter()
+            .flatten()
+            .map(tool_docs)
+            .collect_vec();
+        if !offline
+            && let Some(package) = server.npm_package()
+            && let Some(readme) = npm_readme(package).await
+        {
+            docs.push(readme);
+        }
+
+        for (var, opt) in map.iter().sorted() {
+            let default = defaults.get(opt).cloned();
+            let secret = looks_secret(var) || looks_secret(opt);
+            let option = options
+                .entry(opt.clone())
+                .or_insert_with(|| InferredOption {
+                    kind: default
+                        .as_deref()
+                        .map_or(OptionType::String, OptionType::infer),
+                    description: docs
+                        .iter()
+                        .find_map(|docs| describe(docs, var))
+                        .unwrap_or_else(|| {
+                            format!("The `{var}` {what} of the '{server_name}' MCP server")
+                        }),
+                    default,
+                    secret,
+                });
+            // shared between servers - a secret for one is a secret for all
+            option.secret |= secret;
+        }
+    }
+    options
+}
+
+/// A tool's description & the descriptions of its arguments, one per line
+fn tool_docs(tool: &Tool) -> String {
+    let mut docs = tool.description.as_deref().unwrap_or_default().to_string();
+    if let Some(properties) = tool
+        .input_schema
+        .get("properties")
+        .and_then(|p| p.as_object())
+    {
+        for (name, schema) in properties {
+            if let Some(description) = schema.get("description").and_then(|d| d.as_str()) {
+                docs.push_str(&format!("\n{name}: {description}"));
+            }
+        }
+    }
+    docs
+}
+
+async fn npm_readme(package: &str) -> Option<String> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .ok()?;
+    let packument = client
+        .get(format!(
+            "https://registry.npmjs.org/{}",
+            package.replace('/', "%2F")
+        ))
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?
+        .text()
+        .await
+        .ok()?;
+    let packument: serde_json::Value = serde_json::from_str(&packument).ok()?;
+    packument.get("readme")?.as_str().map(str::to_string)
+}
+
+/// Finds a description of `name` in markdown `docs`: a table row mentioning it, or a line like
+/// ``- `NAME`: what it is``. Code like `NAME=value` or `"NAME": "<value>"` is skipped.
+fn describe(docs: &str, name: &str) -> Option<String> {
+    let name_re = Regex::new(&format!(r"\b{}\b", regex::escape(name))).unwrap();
+    for line in docs.lines().map(str::trim) {
+        let Some(m) = name_re.find(line) else {
+            continue;
+        };
+        let description = match line.starts_with('|') {
+            true => line
+                .split('|')
+                .map(clean)
+                .filter(|cell| !cell.contains(name))
+                .max_by_key(String::len),
+            false => {
+                let rest = line[m.end()..]
+                    .trim_start_matches(['`', '*', '_', '"', '\''])
+                    .trim_start();
+                match rest.starts_with(['=', '"']) || rest.starts_with(": \"") {
+                    true => None,
+                    false => Some(clean(rest.trim_start_matches([':', '-', '–', '—', ' ']))),
+                }
+            }
+        };
+        if let Some(description) = description
+            && description.len() >= 10
+            && description.starts_with(char::is_alphabetic)
+        {
+            return Some(description);
+        }
+    }
+    None
+}
+
+/// First sentence of some markdown, as plain text
+fn clean(markdown: &str) -> String {
+    let text = markdown.replace("**", "").replace('`', "");
+    let text = text.trim();
+    let text = match text.find(". ") {
+        Some(end) => &text[..=end],
+        None => text,
+    };
+    text.chars().take(200).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_from_tables_and_lists() {
+        let docs = "\
+| Variable | Description |
+| --- | --- |
+| `API_KEY` | Your API key for the service. Get one at example.com |
+
+- `BASE_URL`: Base URL of the API to talk to
+";
+        assert_eq!(
+            describe(docs, "API_KEY").as_deref(),
+            Some("Your API key for the service.")
+        );
+        assert_eq!(
+            describe(docs, "BASE_URL").as_deref(),
+            Some("Base URL of the API to talk to")
+        );
+        assert_eq!(describe(docs, "PORT"), None);
+    }
+
+    #[test]
+    fn skips_code_and_short_descriptions() {
+        assert_eq!(describe("API_KEY=sk-123 npx server", "API_KEY"), None);
+        assert_eq!(describe(r#""API_KEY": "<key>""#, "API_KEY"), None);
+        assert_eq!(describe("- `API_KEY`: the key", "API_KEY"), None);
+        // a longer name isn't the one described
+        assert_eq!(
+            describe("- `API_KEY_ID`: Identifier of the key to use", "API_KEY"),
+            None
+        );
+    }
+
+    #[test]
+    fn cleans_to_the_first_sentence() {
+        assert_eq!(clean("**Bold** and `code`. More."), "Bold and code.");
+        assert_eq!(clean("  no end  "), "no end");
+        assert_eq!(clean(&"a".repeat(300)).len(), 200);
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use rmcp::model::Tool;
+
+/// Bounds of a description's length, in words
+const MIN_WORDS: usize = 10;
+const MAX_WORDS: usize = 50;
+
+/// Describes an agent from its MCP servers' tools without an LLM - the same tools always give the
+/// same description.
+///
+/// Each server gets a sentence listing what its tools do, e.g. "With filesystem it can read file
+/// by path, write file and list directory.", filled round-robin so every server is mentioned
+/// before the description runs out of words.
+pub fn summarize(tools: &HashMap<String, Vec<Tool>>) -> String {
+    let servers = tools
+        .iter()
+        .sorted_by_key(|(name, _)| *name)
+        .map(|(name, tools)| {
+            let phrases = tools
+                .iter()
+                .sorted_by(|a, b| a.name.cmp(&b.name))
+                .map(phrase)
+                .dedup()
+                .collect_vec();
+            (name.as_str(), phrases)
+        })
+        .collect_vec();
+
+    let names = servers.iter().map(|(name, _)| *name).collect_vec();
+    let tool_count: usize = tools.values().map(Vec::len).sum();
+    if tool_count == 0 {
+        return match names.is_empty() {
+            true => "An agent without any MCP servers yet, ready to be given tools that other \
+                     agents can ask it to use."
+                .to_string(),
+            false => fit(&names, |servers| {
+                format!(
+                    "An agent built around {servers}, relaying requests from other agents to it."
+                )
+            }),
+        };
+    }
+
+    let intro = fit(&names, |servers| {
+        format!(
+            "An agent exposing {tool_count} {} from {servers}.",
+            plural(tool_count, "tool"),
+        )
+    });
+
+    // a server's sentence costs "With <server> it can" and "and more" on top of its phrases
+    let mut words = word_count(&intro);
+    let mut picked = vec![0; servers.len()];
+    'fill: loop {
+        let mut progressed = false;
+        for (i, (name, phrases)) in servers.iter().enumerate() {
+            let Some(phrase) = phrases.get(picked[i]) else {
+                continue;
+            };
+            let overhead = match picked[i] {
+                0 => 3 + word_count(name) + 2,
+                _ => 0,
+            };
+            let cost = overhead + word_count(phrase);
+            if words + cost > MAX_WORDS {
+                break 'fill;
+            }
+            words += cost;
+            picked[i] += 1;
+            progressed = true;
+        }
+        if !progressed {
+            break;
+        }
+    }
+
+    let mut description = intro;
+    for ((name, phrases), &count) in servers.iter().zip(&picked) {
+        if count == 0 {
+            continue;
+        }
+        let mut shown = phrases[..count].iter().map(String::as_str).collect_vec();
+        if count < phrases.len() {
+            shown.push("more");
+        }
+        description.push_str(&format!(" With {name} it can {}.", join_and(&shown)));
+    }
+    if word_count(&description) < MIN_WORDS {
+        description.push_str(" Other agents can ask it to use these tools for them.");
+    }
+    description
+}
+
+/// The longest of `sentence` naming the MCP servers `names` that's at most [`MAX_WORDS`] long -
+/// naming them all, the first few and how many more, or just how many there are
+fn fit(names: &[&str], sentence: impl Fn(&str) -> String) -> String {
+    let servers = plural(names.len(), "server");
+    (0..=names.len())
+        .rev()
+        .map(|shown| match shown {
+            0 => format!("{} MCP {servers}", names.len()),
+            _ if shown == names.len() => format!("the {} MCP {servers}", join_and(names)),
+            _ => format!(
+                "the {} and {} more MCP {servers}",
+                names[..shown].join(", "),
+                names.len() - shown
+            ),
+        })
+        .map(|servers| sentence(&servers))
+        .find(|sentence| word_count(sentence) <= MAX_WORDS)
+        .unwrap_or_else(|| sentence(&format!("{} MCP {servers}", names.len())))
+}
+
+/// What a tool does, from its name - or from its description when the name is a single word -
+/// plus the argument it's keyed by when it has exactly one required argument
+fn phrase(tool: &Tool) -> String {
+    let mut phrase = humanize(&tool.name);
+    if !phrase.contains(' ')
+        && let Some(description) = tool.description.as_deref()
+    {
+        let first_sentence = description.split(['.', '\n']).next().unwrap_or_default();
+        let words = first_sentence.split_whitespace().take(6).collect_vec();
+        if words.len() >= 2 {
+            phrase = lowercase_first(&words.join(" "));
+        }
+    }
+
+    let required = tool
+        .input_schema
+        .get("required")
+        .and_then(|r| r.as_array())
+        .map(|r| r.iter().filter_map(|v| v.as_str()).collect_vec())
+        .unwrap_or_default();
+    if let [arg] = required.as_slice() {
+        let arg = humanize(arg);
+        if !phrase.contains(&arg) {
+            phrase = format!("{phrase} by {arg}");
+        }
+    }
+    phrase
+}
+
+/// `read_file`, `readFile` and `read-file` all become "read file"
+fn humanize(name: &str) -> String {
+    let mut words = vec![];
+    let mut word = String::new();
+    for c in name.chars() {
+        if c == '_' || c == '-' || c == '.' || c == ' ' {
+            words.push(std::mem::take(&mut word));
+        } else if c.is_uppercase() && word.chars().last().is_some_and(char::is_lowercase) {
+            words.push(std::mem::replace(&mut word, c.to_lowercase().collect()));
+        } else {
+            word.extend(c.to_lowercase());
+        }
+    }
+    words.push(word);
+    words.into_iter().filter(|w| !w.is_empty()).join(" ")
+}
+
+fn lowercase_first(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        // keep acronyms like "URL" as they are
+        Some(c) if !chars.next().is_some_and(char::is_uppercase) => {
+            c.to_lowercase().chain(s.chars().skip(1)).collect()
+        }
+        _ => s.to_string(),
+    }
+}
+
+/// "a", "a and b", "a, b and c"
+fn join_and(items: &[&str]) -> String {
+    match items {
+        [] => String::new(),
+        [item] => item.to_string(),
+        [rest @ .., last] => format!("{} and {last}", rest.join(", ")),
+    }
+}
+
+fn plural(n: usize, word: &str) -> String {
+    match n {
+        1 => word.to_string(),
+        _ => format!("{word}s"),
+    }
+}
+
+fn word_count(s: &str) -> usize {
+    s.split_whitespace().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn tool(name: &str, description: &str) -> Tool {
+        Tool::new(
+            name.to_string(),
+            description.to_string(),
+            Arc::new(serde_json::Map::new()),
+        )
+    }
+
+    fn server(tools: &[&str]) -> Vec<Tool> {
+        tools.iter().map(|name| tool(name, "")).collect()
+    }
+
+    fn assert_bounded(description: &str) {
+        let words = word_count(description);
+        assert!(
+            (MIN_WORDS..=MAX_WORDS).contains(&words),
+            "{words} words: {description}"
+        );
+    }
+
+    #[test]
+    fn no_servers() {
+        assert_bounded(&summarize(&HashMap::new()));
+    }
+
+    #[test]
+    fn servers_without_tools() {
+        let tools = HashMap::from([("fs".to_string(), vec![])]);
+        assert_bounded(&summarize(&tools));
+
+        let many = (0..40).map(|i| (format!("server{i:02}"), vec![])).collect();
+        let description = summarize(&many);
+        assert_bounded(&description);
+        assert!(description.contains("more MCP servers"), "{description}");
+    }
+
+    #[test]
+    fn short_descriptions_are_padded() {
+        let tools = HashMap::from([("fs".to_string(), server(&["read"]))]);
+        let description = summarize(&tools);
+        assert_bounded(&description);
+        assert!(description.starts_with("An agent exposing 1 tool from the fs MCP server."));
+    }
+
+    #[test]
+    fn long_descriptions_are_cut() {
+        let tools = HashMap::from([
+            (
+                "filesystem".to_string(),
+                server(&[
+                    "read_file",
+                    "write_file",
+                    "list_directory",
+                    "move_file",
+                    "search_files",
+                    "get_file_info",
+                    "create_directory",
+                ]),
+            ),
+            (
+                "github".to_string(),
+                server(&[
+                    "create_issue",
+                    "list_pull_requests",
+                    "merge_pull_request",
+                    "search_repositories",
+                    "get_file_contents",
+                    "create_branch",
+                ]),
+            ),
+        ]);
+        let description = summarize(&tools);
+        assert_bounded(&description);
+        assert!(
+            description.contains("With filesystem it can create directory"),
+            "{description}"
+        );
+        assert!(
+            description.contains("With github it can create branch"),
+            "{description}"
+        );
+        assert!(description.contains("and more."), "{description}");
+        assert_eq!(description, summarize(&tools));
+    }
+
+    #[test]
+    fn many_servers_fit() {
+        let tools = (0..30)
+            .map(|i| (format!("server-number-{i}"), server(&["a_tool", "b_tool"])))
+            .collect();
+        let description = summarize(&tools);
+        assert_bounded(&description);
+        assert!(description.starts_with("An agent exposing 60 tools from"));
+    }
+
+    #[test]
+    fn counts_tools_rather_than_phrases() {
+        let tools = HashMap::from([("fs".to_string(), server(&["read_file", "readFile"]))]);
+        assert!(summarize(&tools).starts_with("An agent exposing 2 tools"));
+    }
+
+    #[test]
+    fn phrases() {
+        assert_eq!(humanize("readFile"), "read file");
+        assert_eq!(humanize("list-pull_requests"), "list pull requests");
+        assert_eq!(
+            phrase(&tool("search", "Searches the web for pages. Slowly.")),
+            "searches the web for pages"
+        );
+        assert_eq!(phrase(&tool("fetch", "URL fetcher")), "URL fetcher");
+    }
+}
@@ -0,0 +1,351 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context as _, bail};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::Deserialize;
+use toml_edit::{DocumentMut, Item, Table, TableLike, value};
+
+/// How a template is turned into an agent, declared by the template itself in a
+/// `coralizer.template.toml` at its root - so a template doesn't need framework code to find its
+/// way around it. Templates without one are handled by their framework's built-in rules.
+///
+/// ```toml
+/// # left out of the agent, as gitignore-style patterns
+/// exclude = ["flake.*", ".github/"]
+/// # rendered with MiniJinja, given the agent's `name`, `description`, `servers`, `options` and
+/// # `runtimes` - see [`TemplateContext`](crate::frameworks::TemplateContext)
+/// render = ["main.py", "pyproject.toml"]
+/// # rendered for each MCP server - as `server` - at the injection points of files that aren't
+/// # rendered, or that `add-mcp` adds to, and includable in rendered files as "server"
+/// server = """
+/// "{{ server.name }}": {"transport": "{{ server.transport }}", "url": "{{ server.url }}"},
+/// """
+///
+/// # files the MCP servers are injected into
+/// [[inject]]
+/// file = "main.py"
+/// # a line the servers go before, kept so `add-mcp` can add more later
+/// marker = "# coralizer: mcp servers"
+///
+/// [[inject]]
+/// file = "src/main.rs"
+/// # replaced by the servers
+/// placeholder = "/* coralizer: mcp servers */"
+///
+/// # run in order once the agent is scaffolded - `{name}` and `{description}` are the agent's
+/// [[post_process]]
+/// file = "pyproject.toml"
+/// set = "project.name"
+/// value = "{name}"
+///
+/// [[post_process]]
+/// run = ["uv", "lock"]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateManifest {
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    render: Vec<String>,
+    /// MiniJinja template of one MCP server's code
+    pub server: Option<String>,
+    #[serde(default)]
+    inject: Vec<InjectionPoint>,
+    #[serde(default)]
+    post_process: Vec<PostProcessStep>,
+
+    #[serde(skip, default = "Gitignore::empty")]
+    excluded: Gitignore,
+    #[serde(skip, default = "Gitignore::empty")]
+    rendered: Gitignore,
+}
+
+/// Where the MCP servers go in one of a template's files
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InjectionPoint {
+    /// Relative to the template's root
+    file: PathBuf,
+    marker: Option<String>,
+    placeholder: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum PostProcessStep {
+    /// Sets the dotted key `set` of the TOML `file` to `value`
+    SetToml {
+        file: PathBuf,
+        set: String,
+        value: String,
+    },
+    /// Runs a command in the agent's root
+    Run { run: Vec<String> },
+}
+
+impl TemplateManifest {
+    pub const FILE: &str = "coralizer.template.toml";
+
+    /// Reads the manifest at the root of a template - or of an agent scaffolded from one - if it
+    /// has one
+    pub fn load(root: &Path) -> anyhow::Result<Option<Self>> {
+        let path = root.join(Self::FILE);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("couldn't read '{}'", path.display())),
+        };
+        let mut manifest: TemplateManifest = toml_edit::de::from_str(&contents)
+            .with_context(|| format!("invalid template manifest '{}'", path.display()))?;
+
+        for point in &manifest.inject {
+            if point.marker.is_some() == point.placeholder.is_some() {
+                bail!(
+                    "'{}' in '{}' needs exactly one of `marker` and `placeholder`",
+                    point.file.display(),
+                    path.display()
+                );
+            }
+        }
+        manifest.excluded = patterns(root, &manifest.exclude)?;
+        manifest.rendered = patterns(root, &manifest.render)?;
+        Ok(Some(manifest))
+    }
+
+    /// Whether the template's files are rendered by coralizer's templating engine rather than
+    /// having the MCP servers injected by the framework's code
+    pub fn is_declarative(&self) -> bool {
+        !self.render.is_empty() || self.server.is_some()
+    }
+
+    /// Whether the file at `rel_path` is rendered as a whole
+    pub fn renders(&self, rel_path: &Path) -> bool {
+        self.rendered.matched(rel_path, false).is_ignore()
+    }
+
+    /// Whether `path` - in the template's root - is left out of the agent
+    pub fn excludes(&self, path: &Path, is_dir: bool) -> bool {
+        self.excluded
+            .matched_path_or_any_parents(path, is_dir)
+            .is_ignore()
+    }
+
+    /// Whether the MCP servers are injected into the file at `rel_path`
+    pub fn is_templated_file(&self, rel_path: &Path) -> bool {
+        self.inject.iter().any(|point| point.file == rel_path)
+    }
+
+    /// Injects code at every injection point of the file at `rel_path` in its `contents`. `render`
+    /// is given the indentation of the point and returns the code, each line of it indented and
+    /// ending in a newline. `None` if a point is missing from `contents`.
+    pub fn inject(
+        &self,
+        rel_path: &Path,
+        contents: &str,
+        render: impl Fn(&str) -> anyhow::Result<String>,
+    ) -> anyhow::Result<Option<String>> {
+        let mut contents = contents.to_string();
+        for point in self.inject.iter().filter(|point| point.file == rel_path) {
+            let anchor = point.marker.as_ref().or(point.placeholder.as_ref());
+            let Some((anchor, at)) = anchor.and_then(|a| Some((a, contents.find(a.as_str())?)))
+            else {
+                return Ok(None);
+            };
+            let line_start = contents[..at].rfind('\n').map_or(0, |i| i + 1);
+            let indent: String = contents[line_start..]
+                .chars()
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .collect();
+            let code = render(&indent)?;
+            match point.marker {
+                Some(_) => contents.insert_str(line_start, &code),
+                // the placeholder already sits after the indentation, and before a newline
+                None => contents.replace_range(
+                    at..at + anchor.len(),
+                    code.strip_prefix(&indent).unwrap_or(&code).trim_end(),
+                ),
+            }
+        }
+        Ok(Some(contents))
+    }
+
+    /// A description of where the MCP servers go in the file at `rel_path`, for errors
+    pub fn describe_injection(&self, rel_path: &Path) -> String {
+        self.inject
+            .iter()
+            .filter(|point| point.file == rel_path)
+            .filter_map(|point| point.marker.as_ref().or(point.placeholder.as_ref()))
+            .map(|anchor| format!("'{anchor}'"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Runs the post-process steps in the agent's `root` - each command only once `confirm` agrees
+    /// to it, skipping the ones it refuses
+    pub fn post_process(
+        &self,
+        root: &Path,
+        name: &str,
+        description: &str,
+        confirm: impl Fn(&str) -> anyhow::Result<bool>,
+    ) -> anyhow::Result<()> {
+        let fill = |s: &str| {
+            s.replace("{name}", name)
+                .replace("{description}", description)
+        };
+        for step in &self.post_process {
+            match step {
+                PostProcessStep::SetToml {
+                    file,
+                    set,
+                    value: to,
+                } => {
+                    println!(
+                        "🔧 {:>18} fixup",
+                        console::style(format!("'{}'", file.display())).blue()
+                    );
+                    let path = root.join(file);
+                    let contents = fs::read_to_string(&path)
+                        .with_context(|| format!("couldn't read '{}'", path.display()))?;
+                    let mut toml: DocumentMut = contents
+                        .parse()
+                        .with_context(|| format!("invalid TOML in '{}'", path.display()))?;
+                    let keys = set.split('.').collect::<Vec<_>>();
+                    let Some((last, parents)) = keys.split_last().filter(|_| !keys.contains(&""))
+                    else {
+                        bail!("invalid key '{set}' to set in '{}'", path.display());
+                    };
+                    let mut table = toml.as_table_mut() as &mut dyn TableLike;
+                    for key in parents {
+                        table = table
+                            .entry(key)
+                            .or_insert_with(|| {
+                                let mut table = Table::new();
+                                table.set_implicit(true);
+                                Item::Table(table)
+                            })
+                            .as_table_like_mut()
+                            .with_context(|| {
+                                format!(
+                                    "can't set '{set}' in '{}' - '{key}' isn't a table",
+                                    path.display()
+                                )
+                            })?;
+                    }
+                    table.insert(last, value(fill(to)));
+                    fs::write(&path, toml.to_string())?;
+                }
+                PostProcessStep::Run { run } => {
+                    let Some((program, args)) = run.split_first() else {
+                        continue;
+                    };
+                    let args: Vec<_> = args.iter().map(|arg| fill(arg)).collect();
+                    let command = [program.as_str()]
+                        .into_iter()
+                        .chain(args.iter().map(String::as_str))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    println!(
+                        "🔧 {:>18} run",
+                        console::style(format!("`{command}`")).blue()
+                    );
+                    if !confirm(&command)? {
+                        println!("⏭️ skipped `{command}`");
+                        continue;
+                    }
+                    let status = Command::new(program)
+                        .args(&args)
+                        .current_dir(root)
+                        .status()
+                        .with_context(|| format!("couldn't run `{program}`"))?;
+                    if !status.success() {
+                        bail!("`{}` failed", run.join(" "));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Gitignore-style `patterns`, relative to `root`
+fn patterns(root: &Path, patterns: &[String]) -> anyhow::Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in patterns {
+        builder
+            .add_line(None, pattern)
+            .with_context(|| format!("invalid pattern '{pattern}'"))?;
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    fn manifest(toml: &str) -> TemplateManifest {
+        toml_edit::de::from_str(toml).unwrap()
+    }
+
+    fn set(pyproject: &str, key: &str) -> anyhow::Result<String> {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("pyproject.toml"), pyproject).unwrap();
+        manifest(&format!(
+            "[[post_process]]\nfile = \"pyproject.toml\"\nset = \"{key}\"\nvalue = \"{{name}}\""
+        ))
+        .post_process(dir.path(), "my-agent", "", |_| Ok(true))?;
+        Ok(fs::read_to_string(dir.path().join("pyproject.toml")).unwrap())
+    }
+
+    #[test]
+    fn sets_toml_keys() {
+        assert_eq!(
+            set("[project]\nname = \"template\"\n", "project.name").unwrap(),
+            "[project]\nname = \"my-agent\"\n"
+        );
+        assert_eq!(
+            set("tool = { uv = {} }\n", "tool.uv.name").unwrap(),
+            "tool = { uv = { name = \"my-agent\" } }\n"
+        );
+        assert_eq!(
+            set("", "tool.agent.name").unwrap(),
+            "[tool.agent]\nname = \"my-agent\"\n"
+        );
+    }
+
+    #[test]
+    fn refuses_to_set_keys_under_values() {
+        let e = set("project = \"template\"\n", "project.name").unwrap_err();
+        assert!(e.to_string().contains("'project' isn't a table"), "{e}");
+        assert!(set("", "project..name").is_err());
+    }
+
+    #[test]
+    fn runs_only_confirmed_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = manifest(
+            "[[post_process]]\nrun = [\"touch\", \"{name}\"]\n\n\
+             [[post_process]]\nrun = [\"touch\", \"refused\"]",
+        );
+        let asked = RefCell::new(vec![]);
+        manifest
+            .post_process(dir.path(), "confirmed", "", |command| {
+                asked.borrow_mut().push(command.to_string());
+                Ok(command != "touch refused")
+            })
+            .unwrap();
+        assert_eq!(asked.into_inner(), ["touch confirmed", "touch refused"]);
+        assert!(dir.path().join("confirmed").exists());
+        assert!(!dir.path().join("refused").exists());
+
+        let e = manifest
+            .post_process(dir.path(), "", "", |_| bail!("needs confirming"))
+            .unwrap_err();
+        assert_eq!(e.to_string(), "needs confirming");
+    }
+}
@@ -0,0 +1,995 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context as _, bail};
+use futures_util::StreamExt as _;
+use indicatif::ProgressBar;
+use itertools::Itertools as _;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::read::root_dir_common_filter;
+
+/// Where a framework's template is fetched from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TemplateSource {
+    /// A ref of a git repository - downloaded as an archive from GitHub, cloned from anywhere else
+    Git {
+        git: String,
+        rev: String,
+        /// SHA-256 of the archive, checked when it's downloaded
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+    },
+    /// A zip archive of the template, by URL or path
+    Zip {
+        zip: String,
+        /// SHA-256 of the archive
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+    },
+    /// A template directory on disk, used as it is
+    Dir { path: PathBuf },
+}
+
+impl TemplateSource {
+    /// Parses a `--template`: a directory or zip archive on disk, a zip URL, a git repository URL
+    /// (optionally with a `#<rev>`), or just a git ref of `default`'s repository.
+    pub fn parse(template: &str, default: &TemplateSource) -> anyhow::Result<Self> {
+        let path = Path::new(template);
+        if path.is_dir() {
+            return Ok(TemplateSource::Dir {
+                path: path.to_path_buf(),
+            });
+        }
+        if template.starts_with(['/', '.', '~']) && !path.exists() {
+            bail!("template '{template}' doesn't exist");
+        }
+        // anything else ending in .zip may well be a git ref
+        if template.ends_with(".zip") && (path.is_file() || template.contains("://")) {
+            return Ok(TemplateSource::Zip {
+                zip: template.to_string(),
+                sha256: None,
+            });
+        }
+        if template.contains("://") || template.starts_with("git@") {
+            let (git, rev) = template.split_once('#').unwrap_or((template, "HEAD"));
+            return Ok(TemplateSource::Git {
+                git: git.to_string(),
+                rev: rev.to_string(),
+                sha256: None,
+            });
+        }
+        match default {
+            TemplateSource::Git { git, .. } => Ok(TemplateSource::Git {
+                git: git.clone(),
+                rev: template.to_string(),
+                sha256: None,
+            }),
+            _ => bail!("'{template}' is not a directory, zip archive or git repository"),
+        }
+    }
+
+    /// Whether what's fetched from the source never changes, so it can be cached for good
+    fn is_pinned(&self) -> bool {
+        match self {
+            TemplateSource::Git { rev, .. } => {
+                rev.len() == 40 && rev.chars().all(|c| c.is_ascii_hexdigit())
+            }
+            // a local archive may be rebuilt
+            TemplateSource::Zip { zip, .. } => zip.contains("://"),
+            TemplateSource::Dir { .. } => false,
+        }
+    }
+
+    /// The source's archive URL, for sources that have one
+    fn archive_url(&self) -> Option<String> {
+        match self {
+            TemplateSource::Git { git, rev, .. } => {
+                let repo = git.trim_end_matches('/').trim_end_matches(".git");
+                repo.starts_with("https://github.com/")
+                    .then(|| format!("{repo}/archive/{rev}.zip"))
+            }
+            TemplateSource::Zip { zip, .. } => Some(zip.clone()),
+            TemplateSource::Dir { .. } => None,
+        }
+    }
+
+    /// The SHA-256 the source's archive is expected to have, if its definition records one
+    fn sha256(&self) -> Option<&str> {
+        match self {
+            TemplateSource::Git { sha256, .. } | TemplateSource::Zip { sha256, .. } => {
+                sha256.as_deref()
+            }
+            TemplateSource::Dir { .. } => None,
+        }
+    }
+
+    fn cache_key(&self) -> String {
+        let key = match self {
+            // a source whose recorded checksum changes is a different template
+            TemplateSource::Git { git, rev, sha256 } => format!("git {git} {rev} {sha256:?}"),
+            TemplateSource::Zip { zip, sha256 } => format!("zip {zip} {sha256:?}"),
+            TemplateSource::Dir { path } => format!("dir {}", path.display()),
+        };
+        hex(Sha256::digest(key))
+    }
+
+    /// The SHA-256 the source's archive is known to have - from its definition, or else as
+    /// recorded when it was downloaded
+    fn known_sha256(&self, cache: &TemplateCache) -> Option<String> {
+        self.sha256().map(str::to_ascii_lowercase).or_else(|| {
+            let recorded = fs::read_to_string(checksum_path(&cache.artefact_path(self))).ok()?;
+            Some(recorded.trim().to_string())
+        })
+    }
+
+    /// Fetches the template (unless it's in `cache`) and returns the directory it's in. `offline`,
+    /// it has to be cached.
+    pub async fn fetch(&self, cache: &TemplateCache, offline: bool) -> anyhow::Result<PathBuf> {
+        if let TemplateSource::Dir { path } = self {
+            if !path.is_dir() {
+                bail!("template directory '{}' doesn't exist", path.display());
+            }
+            return Ok(path.clone());
+        }
+
+        let Some(url) = self.archive_url() else {
+            let TemplateSource::Git { git, rev, .. } = self else {
+                unreachable!("only git sources lack an archive");
+            };
+            let extracted_path = cache.templates_dir().join(self.cache_key());
+            if (self.is_pinned() || offline) && extracted_path.is_dir() {
+                return Ok(extracted_path);
+            }
+            if offline {
+                bail!("'{git}#{rev}' isn't cached and can't be cloned offline");
+            }
+            return extract(&extracted_path, |path| clone(git, rev, path));
+        };
+
+        if (self.is_pinned() || offline)
+            && !Path::new(&url).is_file()
+            && let Some(sha256) = self.known_sha256(cache)
+            && cache.templates_dir().join(&sha256).is_dir()
+        {
+            return Ok(cache.templates_dir().join(sha256));
+        }
+        let artefact_path = self.archive(&url, cache, offline).await?;
+        let sha256 = sha256_file(&artefact_path)
+            .with_context(|| format!("couldn't read '{}'", artefact_path.display()))?;
+
+        // extracted by the archive's hash, so a template that changes is never mixed with the
+        // files of the one it replaces
+        let extracted_path = cache.templates_dir().join(&sha256);
+        if extracted_path.is_dir() {
+            return Ok(extracted_path);
+        }
+        extract(&extracted_path, |path| {
+            let artefact = File::open(&artefact_path)
+                .with_context(|| format!("couldn't open '{}'", artefact_path.display()))?;
+            let mut archive = zip::ZipArchive::new(artefact)
+                .with_context(|| format!("'{}' is not a zip archive", artefact_path.display()))?;
+            fs::create_dir_all(path)?;
+            Ok(archive.extract_unwrapped_root_dir(path, root_dir_common_filter)?)
+        })
+    }
+
+    /// The path of the source's archive at `url`, downloaded into the cache unless an intact copy
+    /// of it is there already (or, for sources that aren't pinned, unless `offline`)
+    async fn archive(
+        &self,
+        url: &str,
+        cache: &TemplateCache,
+        offline: bool,
+    ) -> anyhow::Result<PathBuf> {
+        if Path::new(url).is_file() {
+            if let Some(expected) = self.sha256() {
+                let actual = sha256_file(Path::new(url))?;
+                if !actual.eq_ignore_ascii_case(expected) {
+                    bail!("'{url}' has SHA-256 {actual}, but the template expects {expected}");
+                }
+            }
+            return Ok(PathBuf::from(url));
+        }
+        let artefact_path = cache.artefact_path(self);
+        if (self.is_pinned() || offline) && is_intact(&artefact_path, self.sha256()) {
+            return Ok(artefact_path);
+        }
+        if offline {
+            bail!(
+                "the template archive '{url}' isn't cached at '{}' - import a bundle that has it \
+                 with `coralizer templates import`",
+                artefact_path.display()
+            );
+        }
+        download(url, &artefact_path, self.sha256()).await?;
+        Ok(artefact_path)
+    }
+}
+
+/// Fills a fresh `extracted_path` with `fill` - in a directory next to it that's moved into place
+/// once complete, so an interrupted run never leaves a partial template behind
+fn extract(
+    extracted_path: &Path,
+    fill: impl FnOnce(&Path) -> anyhow::Result<()>,
+) -> anyhow::Result<PathBuf> {
+    let partial_path = extracted_path.with_extension("part");
+    for path in [extracted_path, &partial_path] {
+        if path.exists() {
+            fs::remove_dir_all(path)?;
+        }
+    }
+    fs::create_dir_all(extracted_path.parent().expect("template in a directory"))?;
+    fill(&partial_path)?;
+    fs::rename(&partial_path, extracted_path)?;
+    Ok(extracted_path.to_path_buf())
+}
+
+/// Where the SHA-256 of a downloaded artefact is recorded, for sources that don't declare one
+fn checksum_path(artefact_path: &Path) -> PathBuf {
+    artefact_path.with_extension("zip.sha256")
+}
+
+/// Whether a cached artefact is complete and unchanged - it matches `expected`, or else the
+/// SHA-256 recorded when it was downloaded
+fn is_intact(artefact_path: &Path, expected: Option<&str>) -> bool {
+    let expected = match expected {
+        Some(expected) => expected.to_string(),
+        None => match fs::read_to_string(checksum_path(artefact_path)) {
+            Ok(recorded) => recorded.trim().to_string(),
+            Err(_) => return false,
+        },
+    };
+    let intact =
+        sha256_file(artefact_path).is_ok_and(|actual| actual.eq_ignore_ascii_case(&expected));
+    if !intact && artefact_path.exists() {
+        eprintln!(
+            "⚠️: cached template archive '{}' is corrupt",
+            artefact_path.display()
+        );
+    }
+    intact
+}
+
+/// Downloads `url` to a temporary file next to `path` and only moves it into place once it's
+/// complete and, if there's an `expected` SHA-256, matches it
+async fn download(url: &str, path: &Path, expected: Option<&str>) -> anyhow::Result<()> {
+    fs::create_dir_all(path.parent().expect("artefact in a directory"))?;
+    let pb = ProgressBar::new(10).with_message("Fetching template...");
+    let response = reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("couldn't download '{url}'"))?;
+    let mut bytes = pb.wrap_stream(response.bytes_stream());
+
+    let partial_path = path.with_extension("zip.part");
+    let mut artefact = File::create(&partial_path)?;
+    let mut hasher = Sha256::new();
+    let written: anyhow::Result<()> = async {
+        while let Some(item) = bytes.next().await {
+            let item = item?;
+            hasher.update(&item);
+            artefact.write_all(&item)?;
+        }
+        artefact.sync_all()?;
+        Ok(())
+    }
+    .await;
+    pb.finish_and_clear();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&partial_path);
+        return Err(e.context(format!("couldn't download '{url}'")));
+    }
+
+    let actual = hex(hasher.finalize());
+    if let Some(expected) = expected
+        && !actual.eq_ignore_ascii_case(expected)
+    {
+        let _ = fs::remove_file(&partial_path);
+        bail!("'{url}' has SHA-256 {actual}, but the template expects {expected}");
+    }
+    fs::rename(&partial_path, path)?;
+    fs::write(checksum_path(path), format!("{actual}\n"))?;
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex(hasher.finalize()))
+}
+
+fn hex(digest: impl AsRef<[u8]>) -> String {
+    digest.as_ref().iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn clone(repo: &str, rev: &str, path: &Path) -> anyhow::Result<()> {
+    let git = |args: &[&str]| -> anyhow::Result<()> {
+        let status = Command::new("git")
+            .args(args)
+            .status()
+            .context("couldn't run git")?;
+        match status.success() {
+            true => Ok(()),
+            false => bail!("`git {}` failed", args.join(" ")),
+        }
+    };
+    let path = path.to_str().context("cache dir is not utf8")?;
+    git(&["clone", "--quiet", repo, path])?;
+    git(&["-C", path, "checkout", "--quiet", rev])
+}
+
+/// Template archives and extracted templates kept in coralizer's cache dir
+pub struct TemplateCache {
+    dir: PathBuf,
+}
+
+/// An archive or extracted template in the [`TemplateCache`]
+#[derive(Debug, Serialize)]
+pub struct CacheEntry {
+    pub path: PathBuf,
+    /// In bytes - of everything in it, for a directory
+    pub size: u64,
+    /// Whether one of the templates in use needs it
+    pub used: bool,
+}
+
+impl TemplateCache {
+    pub fn new(dir: PathBuf) -> Self {
+        TemplateCache { dir }
+    }
+
+    /// The cache in coralizer's cache dir
+    pub fn in_cache_dir() -> anyhow::Result<Self> {
+        let dirs = directories_next::ProjectDirs::from("com", "coral-protocol", "coralizer")
+            .context("couldn't find a cache dir for coralizer")?;
+        Ok(Self::new(dirs.cache_dir().to_path_buf()))
+    }
+
+    fn artefacts_dir(&self) -> PathBuf {
+        self.dir.join("artefacts")
+    }
+
+    fn templates_dir(&self) -> PathBuf {
+        self.dir.join("templates")
+    }
+
+    fn artefact_path(&self, source: &TemplateSource) -> PathBuf {
+        self.artefacts_dir()
+            .join(format!("{}.zip", source.cache_key()))
+    }
+
+    /// Names of the cache entries fetching `sources` would use
+    fn used(&self, sources: impl IntoIterator<Item = TemplateSource>) -> HashSet<OsString> {
+        let mut used = HashSet::new();
+        for source in sources {
+            let local_archive = match &source {
+                TemplateSource::Dir { .. } => continue,
+                TemplateSource::Zip { zip, .. } if Path::new(zip).is_file() => Some(zip),
+                _ => None,
+            };
+            let key = source.cache_key();
+            match source.archive_url() {
+                Some(_) => used.insert(format!("{key}.zip").into()),
+                // cloned straight into the templates
+                None => used.insert(key.into()),
+            };
+            let sha256 = match local_archive {
+                Some(zip) => sha256_file(Path::new(zip)).ok(),
+                None => source.known_sha256(self),
+            };
+            used.extend(sha256.map(OsString::from));
+        }
+        used
+    }
+
+    /// Everything in the cache, sorted by path
+    pub fn entries(
+        &self,
+        sources: impl IntoIterator<Item = TemplateSource>,
+    ) -> io::Result<Vec<CacheEntry>> {
+        let used = self.used(sources);
+        let mut entries = Vec::new();
+        for dir in [self.artefacts_dir(), self.templates_dir()] {
+            let Ok(children) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for child in children {
+                let child = child?;
+                let path = child.path();
+                // a download's recorded checksum goes with it
+                if path.extension().is_some_and(|ext| ext == "sha256")
+                    && path.with_extension("").is_file()
+                {
+                    continue;
+                }
+                entries.push(CacheEntry {
+                    size: disk_size(&path)?,
+                    used: used.contains(&child.file_name()),
+                    path,
+                });
+            }
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    /// Removes the entries none of `sources` use - like templates that have since been
+    /// re-pinned, or partial downloads - and returns them
+    pub fn prune(
+        &self,
+        sources: impl IntoIterator<Item = TemplateSource>,
+    ) -> io::Result<Vec<CacheEntry>> {
+        let unused: Vec<_> = self
+            .entries(sources)?
+            .into_iter()
+            .filter(|entry| !entry.used)
+            .collect();
+        for entry in &unused {
+            remove(&entry.path)?;
+            let checksum = checksum_path(&entry.path);
+            if checksum.is_file() {
+                fs::remove_file(checksum)?;
+            }
+        }
+        Ok(unused)
+    }
+
+    /// Removes everything and returns how many bytes that freed
+    pub fn clear(&self) -> io::Result<u64> {
+        let freed = self.entries([])?.iter().map(|entry| entry.size).sum();
+        for dir in [self.artefacts_dir(), self.templates_dir()] {
+            if dir.exists() {
+                fs::remove_dir_all(dir)?;
+            }
+        }
+        Ok(freed)
+    }
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    match path.is_dir() {
+        true => fs::remove_dir_all(path),
+        false => fs::remove_file(path),
+    }
+}
+
+fn disk_size(path: &Path) -> io::Result<u64> {
+    let meta = fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    fs::read_dir(path)?.try_fold(0, |size, child| Ok(size + disk_size(&child?.path())?))
+}
+
+/// Index of template sources by framework name, overriding the ones coralizer ships with:
+///
+/// ```toml
+/// [templates.langchain]
+/// git = "https://github.com/Coral-Protocol/langchain-agent"
+/// rev = "v1.2.0"
+/// sha256 = "<SHA-256 of the archive>"
+///
+/// [templates.coral-rs]
+/// path = "../coral-rs-agent"
+/// ```
+///
+/// It also registers frameworks of its own, whose templates are rendered as their
+/// [`TemplateManifest`](crate::template_manifest::TemplateManifest) says:
+///
+/// ```toml
+/// [frameworks.crewai]
+/// name = "CrewAI"
+/// language = "Python"
+/// git = "https://github.com/example/crewai-agent"
+/// rev = "main"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct TemplateRegistry {
+    #[serde(default)]
+    templates: HashMap<String, TemplateSource>,
+    #[serde(default)]
+    pub frameworks: BTreeMap<String, RegisteredFramework>,
+}
+
+/// A framework registered in a [`TemplateRegistry`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisteredFramework {
+    pub name: String,
+    pub language: String,
+    #[serde(flatten)]
+    pub source: TemplateSource,
+}
+
+impl TemplateRegistry {
+    /// Reads the registry at `location` - a path or URL (unless `offline`) - or, by default,
+    /// `templates.toml` in coralizer's config dir if there is one.
+    pub async fn load(location: Option<&str>, offline: bool) -> anyhow::Result<Self> {
+        let (contents, base) = match location {
+            Some(url) if url.starts_with("https://") || url.starts_with("http://") => {
+                if offline {
+                    bail!("can't fetch the template registry '{url}' offline");
+                }
+                let contents = reqwest::get(url).await?.error_for_status()?.text().await?;
+                (contents, None)
+            }
+            Some(path) => (
+                fs::read_to_string(path)
+                    .with_context(|| format!("couldn't read template registry '{path}'"))?,
+                Path::new(path).parent().map(Path::to_path_buf),
+            ),
+            None => return Self::load_default(),
+        };
+        Self::parse(&contents, base)
+    }
+
+    /// Reads `templates.toml` in coralizer's config dir, if there is one
+    pub fn load_default() -> anyhow::Result<Self> {
+        let Some(dirs) = directories_next::ProjectDirs::from("com", "coral-protocol", "coralizer")
+        else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(dirs.config_dir().join("templates.toml")) {
+            Ok(contents) => Self::parse(&contents, Some(dirs.config_dir().to_path_buf())),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Parses a registry whose local templates are relative to `base`
+    fn parse(contents: &str, base: Option<PathBuf>) -> anyhow::Result<Self> {
+        let mut registry: TemplateRegistry =
+            toml_edit::de::from_str(contents).context("invalid template registry")?;
+        // local templates are relative to the registry
+        if let Some(base) = base {
+            let frameworks = registry.frameworks.values_mut();
+            let sources = frameworks.map(|framework| &mut framework.source);
+            for source in registry.templates.values_mut().chain(sources) {
+                match source {
+                    TemplateSource::Dir { path } if path.is_relative() => *path = base.join(&*path),
+                    TemplateSource::Zip { zip, .. } if !zip.contains("://") => {
+                        *zip = base.join(&*zip).to_string_lossy().to_string()
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(registry)
+    }
+
+    /// The source of `framework`'s template - from the registry, or `default` if it has none
+    pub fn source(&self, framework: &str, default: TemplateSource) -> TemplateSource {
+        self.templates.get(framework).cloned().unwrap_or(default)
+    }
+}
+
+/// Template archives bundled into a directory by `coralizer templates export`, for machines
+/// without internet. Its index doubles as a template registry of the bundled archives:
+///
+/// ```toml
+/// [templates.langchain]
+/// zip = "langchain.zip"
+/// sha256 = "..."
+///
+/// [sources.langchain]
+/// git = "https://github.com/Coral-Protocol/langchain-agent"
+/// rev = "..."
+///
+/// [frameworks.crewai]
+/// name = "CrewAI"
+/// language = "Python"
+/// zip = "crewai.zip"
+/// sha256 = "..."
+/// ```
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TemplateBundle {
+    /// The bundled archives by framework
+    #[serde(default)]
+    templates: HashMap<String, TemplateSource>,
+    /// Where each archive was fetched from - what importing it caches it as
+    #[serde(default)]
+    sources: HashMap<String, TemplateSource>,
+    /// The registered frameworks among them, with their bundled archive as their template
+    #[serde(default)]
+    frameworks: BTreeMap<String, RegisteredFramework>,
+}
+
+impl TemplateBundle {
+    /// The bundle's index, in its directory
+    pub const INDEX: &str = "templates.toml";
+
+    /// Bundles the archive of each framework's template into `dir`, fetched through `cache`,
+    /// returning the frameworks bundled. Directories and templates that are cloned with git have
+    /// no archive to bundle. Those of the `registered` frameworks stay registered in the index.
+    pub async fn export(
+        sources: &[(String, TemplateSource)],
+        registered: &BTreeMap<String, RegisteredFramework>,
+        dir: &Path,
+        cache: &TemplateCache,
+    ) -> anyhow::Result<Vec<String>> {
+        // the index is written even if there's nothing to bundle
+        fs::create_dir_all(dir).with_context(|| format!("couldn't create '{}'", dir.display()))?;
+        let mut bundle = TemplateBundle::default();
+        for (framework, source) in sources {
+            let Some(url) = source.archive_url() else {
+                eprintln!("⚠️: the {framework} template has no archive to bundle, skipping it");
+                continue;
+            };
+            let archive = source.archive(&url, cache, false).await?;
+            let zip = format!("{framework}.zip");
+            copy(&archive, &dir.join(&zip))?;
+            let sha256 = Some(sha256_file(&archive)?);
+            let bundled = TemplateSource::Zip { zip, sha256 };
+            if let Some(registered) = registered.get(framework) {
+                let source = bundled.clone();
+                let registered = RegisteredFramework {
+                    source,
+                    ..registered.clone()
+                };
+                bundle.frameworks.insert(framework.to_string(), registered);
+            }
+            bundle.templates.insert(framework.to_string(), bundled);
+            bundle.sources.insert(framework.to_string(), source.clone());
+        }
+        fs::write(
+            dir.join(Self::INDEX),
+            toml_edit::ser::to_string_pretty(&bundle)?,
+        )?;
+        Ok(bundle.templates.into_keys().sorted().collect())
+    }
+
+    /// Caches the archives bundled in `dir` in `cache`, as those of the sources they were
+    /// exported from, returning the frameworks imported
+    pub fn import(dir: &Path, cache: &TemplateCache) -> anyhow::Result<Vec<String>> {
+        let index = dir.join(Self::INDEX);
+        let contents = fs::read_to_string(&index)
+            .with_context(|| format!("couldn't read '{}'", index.display()))?;
+        let bundle: TemplateBundle =
+            toml_edit::de::from_str(&contents).context("invalid template bundle")?;
+
+        for (framework, source) in &bundle.sources {
+            let Some(TemplateSource::Zip { zip, sha256 }) = bundle.templates.get(framework) else {
+                bail!("the bundle has no archive of the {framework} template");
+            };
+            let archive = dir.join(zip);
+            let actual = sha256_file(&archive)
+                .with_context(|| format!("couldn't read '{}'", archive.display()))?;
+            if let Some(expected) = sha256
+                && !actual.eq_ignore_ascii_case(expected)
+            {
+                bail!(
+                    "'{}' has SHA-256 {actual}, but the bundle expects {expected}",
+                    archive.display()
+                );
+            }
+            let artefact_path = cache.artefact_path(source);
+            copy(&archive, &artefact_path)?;
+            fs::write(checksum_path(&artefact_path), format!("{actual}\n"))?;
+        }
+        Ok(bundle.sources.into_keys().sorted().collect())
+    }
+}
+
+/// Copies an archive to a temporary file next to `to` that's moved into place once complete
+fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to.parent().expect("archive in a directory"))?;
+    let partial_path = to.with_extension("zip.part");
+    fs::copy(from, &partial_path)?;
+    File::open(&partial_path)?.sync_all()?;
+    fs::rename(partial_path, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(git: &str, rev: &str) -> TemplateSource {
+        TemplateSource::Git {
+            git: git.to_string(),
+            rev: rev.to_string(),
+            sha256: None,
+        }
+    }
+
+    fn zip(zip: &str) -> TemplateSource {
+        TemplateSource::Zip {
+            zip: zip.to_string(),
+            sha256: None,
+        }
+    }
+
+    const REPO: &str = "https://github.com/Coral-Protocol/langchain-agent";
+
+    #[test]
+    fn parses_templates() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("template.zip");
+        fs::write(&archive, "").unwrap();
+        let archive = archive.to_str().unwrap();
+        let default = git(REPO, "main");
+        let parse = |template: &str| TemplateSource::parse(template, &default).unwrap();
+
+        assert_eq!(
+            parse(dir.path().to_str().unwrap()),
+            TemplateSource::Dir {
+                path: dir.path().to_path_buf()
+            }
+        );
+        assert_eq!(parse(archive), zip(archive));
+        assert_eq!(
+            parse("https://example.com/template.zip"),
+            zip("https://example.com/template.zip")
+        );
+        assert_eq!(
+            parse("https://gitlab.com/me/agent#v1"),
+            git("https://gitlab.com/me/agent", "v1")
+        );
+        assert_eq!(
+            parse("git@github.com:me/agent.git"),
+            git("git@github.com:me/agent.git", "HEAD")
+        );
+        assert_eq!(parse("v1.2.0"), git(REPO, "v1.2.0"));
+        // not an archive on disk, so a ref
+        assert_eq!(parse("release.zip"), git(REPO, "release.zip"));
+
+        let missing = dir.path().join("missing");
+        assert!(TemplateSource::parse(missing.to_str().unwrap(), &default).is_err());
+        let not_git = TemplateSource::Dir {
+            path: dir.path().to_path_buf(),
+        };
+        assert!(TemplateSource::parse("v1.2.0", &not_git).is_err());
+    }
+
+    #[test]
+    fn pinned_sources() {
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        assert!(git(REPO, sha).is_pinned());
+        assert!(!git(REPO, "v1.2.0").is_pinned());
+        assert!(!git(REPO, &sha[1..]).is_pinned());
+        assert!(!git(REPO, &sha.replace('a', "g")).is_pinned());
+        assert!(zip("https://example.com/template.zip").is_pinned());
+        assert!(!zip("template.zip").is_pinned());
+        assert!(
+            !TemplateSource::Dir {
+                path: PathBuf::from("template")
+            }
+            .is_pinned()
+        );
+    }
+
+    #[test]
+    fn checks_cached_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("template.zip");
+        assert!(!is_intact(&archive, None));
+
+        fs::write(&archive, "archive").unwrap();
+        let sha256 = sha256_file(&archive).unwrap();
+        assert!(is_intact(&archive, Some(&sha256)));
+        assert!(is_intact(&archive, Some(&sha256.to_uppercase())));
+        assert!(!is_intact(&archive, Some(&hex(Sha256::digest("other")))));
+
+        // without an expected checksum, only one recorded at download counts
+        assert!(!is_intact(&archive, None));
+        fs::write(checksum_path(&archive), format!("{sha256}\n")).unwrap();
+        assert!(is_intact(&archive, None));
+        fs::write(&archive, "truncated").unwrap();
+        assert!(!is_intact(&archive, None));
+    }
+
+    #[tokio::test]
+    async fn downloads_only_matching_archives() {
+        use wiremock::matchers::path;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(path("/template.zip"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("archive"))
+            .mount(&server)
+            .await;
+        let url = format!("{}/template.zip", server.uri());
+        let sha256 = hex(Sha256::digest("archive"));
+        let dir = tempfile::tempdir().unwrap();
+        let listing = || fs::read_dir(dir.path()).unwrap().count();
+
+        let tampered = dir.path().join("tampered.zip");
+        let e = download(&url, &tampered, Some(&hex(Sha256::digest("other"))))
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains(&sha256), "{e}");
+        assert_eq!(listing(), 0, "nothing is left behind");
+
+        let missing = format!("{}/missing.zip", server.uri());
+        assert!(download(&missing, &tampered, None).await.is_err());
+        assert_eq!(listing(), 0);
+
+        let archive = dir.path().join("template.zip");
+        download(&url, &archive, Some(&sha256)).await.unwrap();
+        assert_eq!(fs::read_to_string(&archive).unwrap(), "archive");
+        assert!(is_intact(&archive, None));
+        assert_eq!(listing(), 2);
+    }
+
+    /// A cache holding a downloaded and extracted archive of `source`, unique to it
+    fn cache_with(dir: &Path, source: &TemplateSource) -> (TemplateCache, String) {
+        let cache = TemplateCache::new(dir.to_path_buf());
+        let artefact = cache.artefact_path(source);
+        fs::create_dir_all(artefact.parent().unwrap()).unwrap();
+        fs::write(&artefact, source.cache_key()).unwrap();
+        let sha256 = sha256_file(&artefact).unwrap();
+        fs::write(checksum_path(&artefact), format!("{sha256}\n")).unwrap();
+        fs::create_dir_all(cache.templates_dir().join(&sha256)).unwrap();
+        (cache, sha256)
+    }
+
+    #[test]
+    fn cache_entries_in_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let downloaded = git(REPO, "v1");
+        let (cache, sha256) = cache_with(dir.path(), &downloaded);
+        let cloned = git("https://gitlab.com/me/agent", "v1");
+        let local = dir.path().join("local.zip");
+        fs::write(&local, "local").unwrap();
+        let local = zip(local.to_str().unwrap());
+        let local_dir = TemplateSource::Dir {
+            path: dir.path().to_path_buf(),
+        };
+
+        let used = cache.used([downloaded.clone(), cloned.clone(), local.clone(), local_dir]);
+        let expected: HashSet<OsString> = [
+            format!("{}.zip", downloaded.cache_key()),
+            sha256,
+            cloned.cache_key(),
+            format!("{}.zip", local.cache_key()),
+            hex(Sha256::digest("local")),
+        ]
+        .into_iter()
+        .map(OsString::from)
+        .collect();
+        assert_eq!(used, expected);
+    }
+
+    #[test]
+    fn prunes_unused_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let current = git(REPO, "v2");
+        let (cache, sha256) = cache_with(dir.path(), &current);
+        let old = git(REPO, "v1");
+        let (_, old_sha256) = cache_with(dir.path(), &old);
+        let partial = cache.artefacts_dir().join("download.zip.part");
+        fs::write(&partial, "arch").unwrap();
+
+        let pruned = cache.prune([current.clone()]).unwrap();
+        let pruned = pruned.iter().map(|entry| entry.path.clone()).collect_vec();
+        assert_eq!(
+            pruned,
+            [
+                partial,
+                cache.artefact_path(&old),
+                cache.templates_dir().join(old_sha256),
+            ]
+            .into_iter()
+            .sorted()
+            .collect_vec()
+        );
+        assert!(!checksum_path(&cache.artefact_path(&old)).exists());
+
+        let left = cache.entries([current.clone()]).unwrap();
+        assert!(left.iter().all(|entry| entry.used));
+        assert_eq!(
+            left.iter().map(|entry| entry.path.clone()).collect_vec(),
+            [
+                cache.artefact_path(&current),
+                cache.templates_dir().join(sha256)
+            ]
+        );
+        assert!(checksum_path(&cache.artefact_path(&current)).exists());
+    }
+
+    #[test]
+    fn archive_urls() {
+        assert_eq!(
+            git(&format!("{REPO}.git"), "v1").archive_url().as_deref(),
+            Some(format!("{REPO}/archive/v1.zip").as_str())
+        );
+        assert_eq!(
+            git(&format!("{REPO}/"), "v1").archive_url().as_deref(),
+            Some(format!("{REPO}/archive/v1.zip").as_str())
+        );
+        // cloned instead
+        assert_eq!(git("https://gitlab.com/me/agent", "v1").archive_url(), None);
+        assert_eq!(
+            zip("template.zip").archive_url().as_deref(),
+            Some("template.zip")
+        );
+        assert_eq!(
+            TemplateSource::Dir {
+                path: PathBuf::from("template")
+            }
+            .archive_url(),
+            None
+        );
+    }
+
+    /// A zip archive of a template with just `file` in it
+    fn template_zip(file: &str) -> Vec<u8> {
+        let mut archive = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        let options = zip::write::SimpleFileOptions::default();
+        archive
+            .start_file(format!("agent/{file}"), options)
+            .unwrap();
+        archive.write_all(b"contents").unwrap();
+        archive.finish().unwrap().into_inner()
+    }
+
+    #[tokio::test]
+    async fn bundles_templates_for_offline_use() {
+        use wiremock::matchers::path;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(path("/crewai.zip"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(template_zip("main.py")))
+            .mount(&server)
+            .await;
+        let url = format!("{}/crewai.zip", server.uri());
+        let registry = TemplateRegistry::parse(
+            &format!(
+                "[frameworks.crewai]\nname = \"CrewAI\"\nlanguage = \"Python\"\nzip = \"{url}\"\n"
+            ),
+            None,
+        )
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let online = TemplateCache::new(dir.path().join("online"));
+        let local = TemplateSource::Dir {
+            path: dir.path().to_path_buf(),
+        };
+
+        // with every template skipped, there's still an index
+        let empty = dir.path().join("empty");
+        let sources = [("local".to_string(), local.clone())];
+        let exported = TemplateBundle::export(&sources, &BTreeMap::new(), &empty, &online).await;
+        assert!(exported.unwrap().is_empty());
+        assert!(empty.join(TemplateBundle::INDEX).is_file());
+
+        let bundle = dir.path().join("bundle");
+        let source = zip(&url);
+        let sources = [
+            ("crewai".to_string(), source.clone()),
+            ("local".to_string(), local),
+        ];
+        let exported = TemplateBundle::export(&sources, &registry.frameworks, &bundle, &online);
+        assert_eq!(exported.await.unwrap(), ["crewai"]);
+
+        // the index registers the framework with its bundled archive
+        let index = bundle.join(TemplateBundle::INDEX);
+        let index = TemplateRegistry::load(index.to_str(), true).await.unwrap();
+        let crewai = &index.frameworks["crewai"];
+        assert_eq!(
+            (crewai.name.as_str(), crewai.language.as_str()),
+            ("CrewAI", "Python")
+        );
+        assert_eq!(
+            crewai.source.archive_url(),
+            Some(bundle.join("crewai.zip").to_string_lossy().to_string())
+        );
+
+        let offline = TemplateCache::new(dir.path().join("offline"));
+        assert!(source.fetch(&offline, true).await.is_err());
+        assert_eq!(
+            TemplateBundle::import(&bundle, &offline).unwrap(),
+            ["crewai"]
+        );
+        drop(server);
+        let template = source.fetch(&offline, true).await.unwrap();
+        assert_eq!(
+            fs::read_to_string(template.join("main.py")).unwrap(),
+            "contents"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use anyhow::Context as _;
+use rmcp::model::Tool;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::mcp_server::McpServer;
+
+/// Tool listings of MCP servers cached on disk, so servers (and `npx -y` installs) don't have to be
+/// started again on every run. Entries are keyed by a hash of the server's config, so changing the
+/// config in any way lists its tools afresh.
+pub struct ToolCache {
+    dir: PathBuf,
+    /// Ignore cached listings, replacing them with live ones
+    pub refresh: bool,
+}
+
+impl ToolCache {
+    /// The cache in coralizer's cache dir
+    pub fn new(refresh: bool) -> anyhow::Result<Self> {
+        let dirs = directories_next::ProjectDirs::from("com", "coral-protocol", "coralizer")
+            .context("couldn't find a cache dir for coralizer")?;
+        Ok(Self::in_dir(dirs.cache_dir().join("tools"), refresh))
+    }
+
+    pub fn in_dir(dir: PathBuf, refresh: bool) -> Self {
+        ToolCache { dir, refresh }
+    }
+
+    fn path(&self, server: &McpServer) -> PathBuf {
+        self.dir.join(format!("{}.json", cache_key(server)))
+    }
+
+    /// The cached tools of `server`, if there are any and we're not refreshing them
+    pub fn get(&self, server: &McpServer) -> Option<Vec<Tool>> {
+        if self.refresh {
+            return None;
+        }
+        let contents = fs::read_to_string(self.path(server)).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    pub fn put(&self, server: &McpServer, tools: &[Tool]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(server), serde_json::to_string(tools)?)
+    }
+}
+
+fn sorted(map: &Option<HashMap<String, String>>) -> BTreeMap<&String, &String> {
+    map.iter().flatten().collect()
+}
+
+/// Hex SHA-256 of everything about `server` that could change the tools it has
+fn cache_key(server: &McpServer) -> String {
+    let config: Value = match server {
+        McpServer::Stdio {
+            command,
+            args,
+            env,
+            cwd,
+            env_file,
+        } => json!({
+            "type": "stdio",
+            "command": command,
+            "args": args,
+            "env": sorted(env),
+            "cwd": cwd,
+            "envFile": env_file,
+        }),
+        McpServer::Sse { url, headers } => json!({
+            "type": "sse",
+            "url": url,
+            "headers": sorted(headers),
+        }),
+        McpServer::Http { url, headers } => json!({
+            "type": "http",
+            "url": url,
+            "headers": sorted(headers),
+        }),
+    };
+    Sha256::digest(config.to_string())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn map(pairs: &[(&str, &str)]) -> Option<HashMap<String, String>> {
+        Some(
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    fn server(
+        command: &str,
+        args: &[&str],
+        env: &[(&str, &str)],
+        cwd: Option<&str>,
+        env_file: Option<&str>,
+    ) -> McpServer {
+        McpServer::Stdio {
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            env: map(env),
+            cwd: cwd.map(str::to_string),
+            env_file: env_file.map(str::to_string),
+        }
+    }
+
+    fn stdio(env: &[(&str, &str)]) -> McpServer {
+        server("server", &["--stdio"], env, None, None)
+    }
+
+    fn tools(names: &[&str]) -> Vec<Tool> {
+        names
+            .iter()
+            .map(|name| Tool::new(name.to_string(), "", Arc::new(serde_json::Map::new())))
+            .collect()
+    }
+
+    fn names(tools: Option<Vec<Tool>>) -> Option<Vec<String>> {
+        tools.map(|tools| tools.iter().map(|tool| tool.name.to_string()).collect())
+    }
+
+    #[test]
+    fn caches_tools_by_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ToolCache::in_dir(dir.path().join("tools"), false);
+        let server = stdio(&[]);
+        assert!(cache.get(&server).is_none());
+
+        cache.put(&server, &tools(&["read", "write"])).unwrap();
+        assert_eq!(names(cache.get(&server)).unwrap(), ["read", "write"]);
+        assert!(cache.get(&stdio(&[("KEY", "KEY")])).is_none());
+
+        // refreshing skips the cached listing, and replaces it
+        let refreshing = ToolCache::in_dir(dir.path().join("tools"), true);
+        assert!(refreshing.get(&server).is_none());
+        refreshing.put(&server, &tools(&["read"])).unwrap();
+        assert_eq!(names(cache.get(&server)).unwrap(), ["read"]);
+    }
+
+    #[test]
+    fn keys_by_the_whole_config() {
+        let env = [("A", "1"), ("B", "2"), ("C", "3")];
+        let reversed: Vec<_> = env.iter().rev().copied().collect();
+        assert_eq!(cache_key(&stdio(&env)), cache_key(&stdio(&reversed)));
+
+        let key = cache_key(&stdio(&env));
+        let changed = [
+            stdio(&[("A", "1"), ("B", "2"), ("C", "4")]),
+            stdio(&[("A", "1"), ("B", "2")]),
+            server("other", &["--stdio"], &env, None, None),
+            server("server", &[], &env, None, None),
+            server("server", &["--stdio"], &env, Some("/"), None),
+            server("server", &["--stdio"], &env, None, Some(".env")),
+        ];
+        for server in &changed {
+            assert_ne!(cache_key(server), key, "{server:?}");
+        }
+
+        let sse = |url: &str, headers: &[(&str, &str)]| McpServer::Sse {
+            url: url.to_string(),
+            headers: map(headers),
+        };
+        let headers = [("X-A", "1"), ("X-B", "2")];
+        let key = cache_key(&sse("http://localhost/mcp", &headers));
+        let reordered = sse("http://localhost/mcp", &[("X-B", "2"), ("X-A", "1")]);
+        assert_eq!(cache_key(&reordered), key);
+        assert_ne!(cache_key(&sse("http://localhost/sse", &headers)), key);
+        assert_ne!(
+            cache_key(&sse("http://localhost/mcp", &[("X-A", "1")])),
+            key
+        );
+        let http = McpServer::Http {
+            url: "http://localhost/mcp".to_string(),
+            headers: map(&headers),
+        };
+        assert_ne!(cache_key(&http), key);
+    }
+}
@@ -0,0 +1,455 @@
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use ignore::WalkBuilder;
+use regex::Regex;
+use serde::Serialize;
+use toml_edit::{Document, Item};
+
+use crate::agent_config::{CoralAgent, Violation};
+use crate::mcp_server::PLACEHOLDER_DESCRIPTION;
+
+/// Source files the options of an agent are expected to be read in
+const SOURCE_FILES: [&str; 2] = ["main.py", "main.rs"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found in an agent, in a shape that can be printed for humans or as JSON.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: PathBuf,
+    /// Dotted path of the offending key, e.g. `runtimes.docker.image`
+    pub path: String,
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, file: &Path, content: &str, violation: Violation) -> Self {
+        let (line, column) = violation.line_col(content).unzip();
+        Diagnostic {
+            severity,
+            file: file.to_path_buf(),
+            path: violation.path,
+            message: violation.message,
+            line,
+            column,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, ":{line}:{column}")?;
+        }
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, ": {severity}: ")?;
+        match self.path.is_empty() {
+            true => write!(f, "{}", self.message),
+            false => write!(f, "'{}' - {}", self.path, self.message),
+        }
+    }
+}
+
+/// Checks the agent at `path` (its root directory or its `coral-agent.toml`) beyond what the
+/// schema in [`CoralAgent::validate`] covers.
+pub fn validate(path: &Path) -> Vec<Diagnostic> {
+    let (root, toml_path) = match path.is_file() {
+        true => (
+            path.parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .unwrap_or(Path::new("."))
+                .to_path_buf(),
+            path.to_path_buf(),
+        ),
+        false => (path.to_path_buf(), path.join("coral-agent.toml")),
+    };
+    let error = |message: String| Diagnostic {
+        severity: Severity::Error,
+        file: toml_path.clone(),
+        path: String::new(),
+        message,
+        line: None,
+        column: None,
+    };
+
+    let content = match fs::read_to_string(&toml_path) {
+        Ok(content) => content,
+        Err(e) => return vec![error(format!("could not read manifest - {e}"))],
+    };
+    let agent = match CoralAgent::validate(&content) {
+        Ok(agent) => agent,
+        Err(violations) => {
+            return violations
+                .into_iter()
+                .map(|v| Diagnostic::new(Severity::Error, &toml_path, &content, v))
+                .collect();
+        }
+    };
+    // the schema already passed, so this parses and has every key the checks below look at
+    let doc = Document::parse(content.as_str()).expect("valid coral-agent.toml");
+
+    let mut diagnostics = vec![];
+    let mut report = |severity, path: &str, message: String| {
+        diagnostics.push(Diagnostic::new(
+            severity,
+            &toml_path,
+            &content,
+            Violation {
+                span: span(&doc, path),
+                path: path.to_string(),
+                message,
+            },
+        ))
+    };
+
+    if let Err(e) = semver::Version::parse(&agent.agent.version) {
+        report(
+            Severity::Error,
+            "agent.version",
+            format!("'{}' is not a semver version - {e}", agent.agent.version),
+        );
+    }
+
+    match agent.agent.description.as_deref().map(str::trim) {
+        None | Some("") => report(
+            Severity::Error,
+            "agent.description",
+            "missing a description".into(),
+        ),
+        Some(PLACEHOLDER_DESCRIPTION) => report(
+            Severity::Error,
+            "agent.description",
+            format!("still the '{PLACEHOLDER_DESCRIPTION}' placeholder"),
+        ),
+        Some(_) => {}
+    }
+
+    if let Some(docker) = agent.runtimes.as_ref().and_then(|r| r.docker.as_ref())
+        && let Err(e) = check_image(&docker.image)
+    {
+        report(
+            Severity::Error,
+            "runtimes.docker.image",
+            format!("'{}' is not a valid image reference - {e}", docker.image),
+        );
+    }
+
+    let sources = read_sources(&root);
+    if sources.is_empty() {
+        report(
+            Severity::Warning,
+            "",
+            format!(
+                "no {} found, skipped checking that options are used",
+                SOURCE_FILES.join(" or ")
+            ),
+        );
+    } else {
+        let mut options = agent
+            .options
+            .iter()
+            .flatten()
+            .map(|(k, _)| k)
+            .collect::<Vec<_>>();
+        options.sort();
+        for opt in options {
+            let used = Regex::new(&format!(r"\b{}\b", regex::escape(opt))).unwrap();
+            if !sources.iter().any(|src| used.is_match(src)) {
+                report(
+                    Severity::Error,
+                    &format!("options.{opt}"),
+                    format!("not referenced in {}", SOURCE_FILES.join(" or ")),
+                );
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Where the key at dotted `path` is in `doc`
+fn span(doc: &Document<&str>, path: &str) -> Option<Range<usize>> {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => (parent, key),
+        None if path.is_empty() => return None,
+        None => ("", path),
+    };
+    let mut table = doc.as_table() as &dyn toml_edit::TableLike;
+    for part in parent.split('.').filter(|p| !p.is_empty()) {
+        table = table.get(part).and_then(Item::as_table_like)?;
+    }
+    table
+        .get(key)
+        .and_then(Item::span)
+        .or_else(|| table.key(key).and_then(|k| k.span()))
+}
+
+/// Contents of every [`SOURCE_FILES`] under `root`
+fn read_sources(root: &Path) -> Vec<String> {
+    WalkBuilder::new(root)
+        .require_git(false)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry.path().is_file()
+                && entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| SOURCE_FILES.contains(&name))
+        })
+        .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+        .collect()
+}
+
+/// Checks `image` against the grammar of a Docker image reference -
+/// `[registry[:port]/]name[:tag][@digest]`
+fn check_image(image: &str) -> Result<(), &'static str> {
+    let reference = Regex::new(concat!(
+        r"^(?<name>",
+        // registry
+        r"(?:(?:[a-zA-Z0-9]|[a-zA-Z0-9][a-zA-Z0-9-]*[a-zA-Z0-9])",
+        r"(?:\.(?:[a-zA-Z0-9]|[a-zA-Z0-9][a-zA-Z0-9-]*[a-zA-Z0-9]))*(?::[0-9]+)?/)?",
+        // path components
+        r"[a-z0-9]+(?:(?:[._]|__|-+)[a-z0-9]+)*",
+        r"(?:/[a-z0-9]+(?:(?:[._]|__|-+)[a-z0-9]+)*)*",
+        r")",
+        r"(?::[\w][\w.-]{0,127})?",
+        r"(?:@[A-Za-z][A-Za-z0-9]*(?:[-_+.][A-Za-z][A-Za-z0-9]*)*:[0-9A-Fa-f]{32,})?$",
+    ))
+    .unwrap();
+
+    if image.is_empty() {
+        return Err("must not be empty");
+    }
+    let Some(caps) = reference.captures(image) else {
+        return Err("expected [registry/]name[:tag][@digest]");
+    };
+    if caps["name"].len() > 255 {
+        return Err("name must be at most 255 characters");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"edition = 2
+
+[agent]
+name = "agent"
+version = "0.1.0"
+description = "Does things"
+
+[runtimes.executable]
+path = "python"
+arguments = ["main.py"]
+transport = "sse"
+
+[options.API_KEY]
+type = "string"
+required = true
+secret = true
+"#;
+
+    /// Validates an agent with `manifest` and a `source` file of the given name
+    fn validate_agent(manifest: &str, source: Option<(&str, &str)>) -> Vec<Diagnostic> {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("coral-agent.toml"), manifest).unwrap();
+        if let Some((name, contents)) = source {
+            fs::write(root.path().join(name), contents).unwrap();
+        }
+        validate(root.path())
+    }
+
+    fn paths(diagnostics: &[Diagnostic]) -> Vec<(Severity, &str)> {
+        diagnostics
+            .iter()
+            .map(|d| (d.severity, d.path.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn valid_agents() {
+        let langchain = r#"headers = {"Authorization": asserted_env("API_KEY")}"#;
+        let diagnostics = validate_agent(MANIFEST, Some(("main.py", langchain)));
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+
+        // env vars named like their options are exported too
+        let coral_rs = r#"McpConnectionBuilder::stdio("sh", ["-c", "export API_KEY=\"$(printenv 'API_KEY')\" && exec \"$0\" \"$@\"", "server"], "s")"#;
+        let diagnostics = validate_agent(MANIFEST, Some(("main.rs", coral_rs)));
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
+
+    #[test]
+    fn unreferenced_options_are_errors() {
+        let diagnostics = validate_agent(MANIFEST, Some(("main.py", "API_KEY_ID = 1")));
+        assert_eq!(paths(&diagnostics), [(Severity::Error, "options.API_KEY")]);
+        assert!(diagnostics[0].line.is_some());
+    }
+
+    #[test]
+    fn missing_sources_are_a_warning() {
+        let diagnostics = validate_agent(MANIFEST, None);
+        assert_eq!(paths(&diagnostics), [(Severity::Warning, "")]);
+    }
+
+    #[test]
+    fn versions_must_be_semver() {
+        let manifest = MANIFEST.replace("0.1.0", "1.0");
+        let diagnostics = validate_agent(&manifest, Some(("main.py", "API_KEY")));
+        assert_eq!(paths(&diagnostics), [(Severity::Error, "agent.version")]);
+        assert_eq!(diagnostics[0].line, Some(5));
+    }
+
+    #[test]
+    fn descriptions_must_be_filled_in() {
+        for description in ["   ", PLACEHOLDER_DESCRIPTION] {
+            let manifest = MANIFEST.replace("Does things", description);
+            let diagnostics = validate_agent(&manifest, Some(("main.py", "API_KEY")));
+            assert_eq!(
+                paths(&diagnostics),
+                [(Severity::Error, "agent.description")]
+            );
+        }
+    }
+
+    #[test]
+    fn docker_images_must_be_well_formed() {
+        let manifest = format!("{MANIFEST}\n[runtimes.docker]\nimage = \"My Image\"\n");
+        let diagnostics = validate_agent(&manifest, Some(("main.py", "API_KEY")));
+        assert_eq!(
+            paths(&diagnostics),
+            [(Severity::Error, "runtimes.docker.image")]
+        );
+    }
+
+    #[test]
+    fn schema_violations_are_errors() {
+        let diagnostics = validate_agent("[agent]\nname = 1\n", None);
+        assert!(!diagnostics.is_empty());
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+    }
+
+    /// Each diagnostic's path, message and where it is
+    fn locations(diagnostics: &[Diagnostic]) -> Vec<(&str, &str, Option<usize>, Option<usize>)> {
+        diagnostics
+            .iter()
+            .map(|d| (d.path.as_str(), d.message.as_str(), d.line, d.column))
+            .collect()
+    }
+
+    #[test]
+    fn reports_every_schema_violation_where_it_is() {
+        let manifest = MANIFEST
+            .replace(r#"name = "agent""#, "name = 1")
+            .replace(r#"transport = "sse""#, r#"transport = "udp""#);
+        let diagnostics = validate_agent(&manifest, None);
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+        assert_eq!(
+            locations(&diagnostics),
+            [
+                (
+                    "agent.name",
+                    "expected a string, found integer",
+                    Some(4),
+                    Some(8)
+                ),
+                (
+                    "runtimes.executable.transport",
+                    "unknown transport 'udp', expected one of sse, streamable_http",
+                    Some(11),
+                    Some(13)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_every_error_where_it_is() {
+        let manifest = MANIFEST
+            .replace("0.1.0", "1.0")
+            .replace("Does things", PLACEHOLDER_DESCRIPTION);
+        let manifest = format!("{manifest}\n[runtimes.docker]\nimage = \"My Image\"\n");
+        let diagnostics = validate_agent(&manifest, Some(("main.py", "API_KEY_ID = 1")));
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+        assert_eq!(
+            locations(&diagnostics),
+            [
+                (
+                    "agent.version",
+                    "'1.0' is not a semver version - unexpected end of input while parsing minor \
+                     version number",
+                    Some(5),
+                    Some(11)
+                ),
+                (
+                    "agent.description",
+                    "still the 'CHANGE ME' placeholder",
+                    Some(6),
+                    Some(15)
+                ),
+                (
+                    "runtimes.docker.image",
+                    "'My Image' is not a valid image reference - expected \
+                     [registry/]name[:tag][@digest]",
+                    Some(19),
+                    Some(9)
+                ),
+                (
+                    "options.API_KEY",
+                    "not referenced in main.py or main.rs",
+                    Some(13),
+                    Some(1)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn image_references() {
+        let digest = format!("sha256:{}", "a".repeat(64));
+        for image in [
+            "ubuntu".to_string(),
+            "ubuntu:22.04".to_string(),
+            "my-org/my_agent".to_string(),
+            "ghcr.io/org/agent:v1.0".to_string(),
+            "localhost:5000/a/b".to_string(),
+            format!("org/agent@{digest}"),
+            format!("org/agent:1@{digest}"),
+        ] {
+            assert_eq!(check_image(&image), Ok(()), "{image}");
+        }
+        for image in [
+            "",
+            "Ubuntu",
+            "my image",
+            "a//b",
+            "-a",
+            "a:",
+            "a:-tag",
+            "a@sha256:abc",
+        ] {
+            assert!(check_image(image).is_err(), "{image}");
+        }
+        assert_eq!(
+            check_image(&"a".repeat(256)),
+            Err("name must be at most 255 characters")
+        );
+    }
+}